@@ -1,14 +1,24 @@
 use crate::{
-    api::github::GITHUB,
-    utils::{appimage::AppImage, errors::error, tools::Tools},
+    api::{github::GITHUB, options::InstallOptions},
+    utils::{
+        appimage::AppImage,
+        checksum::Checksum,
+        config::Config,
+        errors::error,
+        manifest::{Installed, Manifest, Provider},
+        progress,
+        staging::Staging,
+        version::Version,
+        zsync::Zsync,
+    },
 };
 use anyhow::Result;
+use chrono::{TimeZone, Utc};
 use color_print::{cformat, cprintln};
 use dialoguer::{theme::ColorfulTheme, Confirm, Select};
 use indicatif::ProgressBar;
 use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
 use scraper::{Html, Selector};
-use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::{cmp::Ordering, process::exit, time::Duration};
 
@@ -23,6 +33,7 @@ pub struct Results {
     pub Name: Option<String>,
     pub Popularity: Option<f32>,
     pub Version: Option<String>,
+    pub LastModified: Option<i64>,
 }
 
 impl AUR {
@@ -81,16 +92,22 @@ impl AUR {
         Ok(appimage_url)
     }
 
-    pub async fn get_latest_version(name: &str) -> Result<Version> {
+    /// Latest version of the package and the day it was last modified
+    pub async fn get_latest_version(name: &str) -> Result<(Version, Option<String>)> {
         let url = format!("https://aur.archlinux.org/rpc/v5/info/{}", name);
         let mut headers = HeaderMap::new();
         headers.insert(USER_AGENT, "reqwest".parse().unwrap());
 
         let response = AUR::get(&url).await?;
-        let version = response
+        let result = response
             .results
             .as_ref()
-            .and_then(|results| results.get(0))
+            .and_then(|results| results.first());
+        let modified = result
+            .and_then(|result| result.LastModified)
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+            .map(|date| date.format("%Y-%m-%d").to_string());
+        let version = result
             .and_then(|result| result.Version.as_ref())
             .ok_or_else(|| error!("Failed to get version"))?
             .split('-')
@@ -98,52 +115,168 @@ impl AUR {
             .ok_or_else(|| error!("Failed to split version"))?
             .to_string();
 
-        let latest_version = Version::parse(&version)?;
-        Ok(latest_version)
+        Ok((Version::parse(&version), modified))
     }
 
-    pub async fn download(name: &str) -> Result<()> {
-        let appimage_url = AUR::get_appimage_url(name).await?;
-        let version = AUR::get_latest_version(name).await?;
-        let name = name.replace('-', "_");
+    /// Reads the checksum of the AppImage source from the package PKGBUILD
+    pub async fn get_checksum(name: &str, appimage_url: &str) -> Result<Option<Checksum>> {
+        let pkgbuild = reqwest::get(format!(
+            "https://aur.archlinux.org/cgit/aur.git/plain/PKGBUILD?h={}",
+            name
+        ))
+        .await?
+        .text()
+        .await?;
+
+        let file_name = appimage_url.rsplit('/').next().unwrap_or(appimage_url);
+        let arch = std::env::consts::ARCH;
+        for suffix in ["".to_string(), format!("_{}", arch)] {
+            let sources = AUR::get_array(&pkgbuild, &format!("source{}", suffix));
+            let Some(index) = sources.iter().position(|source| {
+                let url = source.rsplit("::").next().unwrap_or(source);
+                url == appimage_url
+                    || url.ends_with(&format!("/{}", file_name))
+                    || url.to_lowercase().ends_with(".appimage")
+            }) else {
+                continue;
+            };
+            for algorithm in ["sha256sums", "sha512sums"] {
+                let sums = AUR::get_array(&pkgbuild, &format!("{}{}", algorithm, suffix));
+                if let Some(checksum) = sums.get(index).and_then(|sum| Checksum::from_hex(sum)) {
+                    return Ok(Some(checksum));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Extracts the items of a bash array such as `sha256sums=('...' '...')`
+    fn get_array(pkgbuild: &str, key: &str) -> Vec<String> {
+        let prefix = format!("{}=(", key);
+        let Some(start) = pkgbuild
+            .lines()
+            .position(|line| line.trim_start().starts_with(&prefix))
+        else {
+            return Vec::new();
+        };
 
-        let app_folder = format!("/home/{}/Applications/{}", Tools.get_user()?, name);
-        let app_folder_path = std::path::Path::new(&app_folder);
-        if app_folder_path.exists() {
+        let mut content = String::new();
+        for line in pkgbuild.lines().skip(start) {
+            let line = line.split(" #").next().unwrap_or(line);
+            content.push_str(line);
+            content.push(' ');
+            if line.trim_end().ends_with(')') {
+                break;
+            }
+        }
+
+        let content = content.trim();
+        let content = content
+            .strip_prefix(&prefix)
+            .unwrap_or(content)
+            .trim_end_matches(')');
+        content
+            .split_whitespace()
+            .map(|item| item.trim_matches(|c| c == '\'' || c == '"').to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    pub async fn download(name: &str, options: &InstallOptions) -> Result<()> {
+        if name.contains('@') || options.tag.is_some() {
+            return Err(error!(
+                "AUR packages only provide their current version, a release tag cannot be chosen"
+            ));
+        }
+        if options
+            .channel
+            .as_ref()
+            .is_some_and(|channel| !channel.is_stable())
+        {
+            return Err(error!(
+                "AUR packages only provide their current version, a channel cannot be chosen"
+            ));
+        }
+        if options.tag_filter.is_some() {
+            return Err(error!(
+                "AUR packages only provide their current version, a tag filter cannot be chosen"
+            ));
+        }
+        let manifest = Manifest::load()?;
+        if manifest.get(name).is_some() {
             cprintln!("<c>{} <y>is already installed", name);
             return Ok(());
         }
 
-        let file_path = format!(
-            "/home/{}/Applications/{}/{}-AUR-v{}.appimage",
-            Tools.get_user()?,
-            name,
-            name,
-            version
-        );
-
-        let pb = ProgressBar::new_spinner();
-        pb.enable_steady_tick(Duration::from_millis(120));
-        pb.set_message(cformat!("<c>Downloading {}...", name));
-        AppImage.download(&appimage_url, &file_path).await?;
-        pb.finish_and_clear();
+        AUR::install(name, options).await
+    }
 
-        let pb = ProgressBar::new_spinner();
-        pb.enable_steady_tick(Duration::from_millis(120));
-        pb.set_message(cformat!("<c>Installing {}...", name));
-        AppImage.extract(&file_path)?;
-        AppImage.integrate(&file_path, &name)?;
-        pb.finish_and_clear();
+    /// Installs the latest version of `name`, replacing the installed one
+    pub async fn install(name: &str, options: &InstallOptions) -> Result<()> {
+        let appimage_url = AUR::get_appimage_url(name).await?;
+        if appimage_url.is_empty() {
+            return Err(error!("No AppImage found"));
+        }
+        let (version, _) = AUR::get_latest_version(name).await?;
+        let asset_name = appimage_url
+            .rsplit('/')
+            .next()
+            .unwrap_or(&appimage_url)
+            .to_string();
 
-        cprintln!(
+        let config = Config::load()?;
+        let staging = Staging::new(&config, name, &version.to_string())?;
+        let file_path = staging.file_path();
+
+        let downloaded = async {
+            let zsync = match options.seed {
+                Some(_) => Zsync::find(&appimage_url).await,
+                None => None,
+            };
+            AppImage
+                .download_delta(
+                    &appimage_url,
+                    HeaderMap::new(),
+                    &file_path,
+                    name,
+                    zsync.as_ref(),
+                    options.seed.as_deref(),
+                )
+                .await?;
+            AUR::get_checksum(name, &appimage_url).await
+        }
+        .await;
+        let folder = staging.version_folder();
+        let (files, digest, update_info) = AppImage
+            .install_staged(staging, downloaded, options.skip_verify, &config)
+            .await?;
+
+        Manifest::update(|manifest| {
+            manifest.insert_version(
+                Installed {
+                    tag: version.to_string(),
+                    version: version.to_string(),
+                    asset: asset_name,
+                    url: appimage_url,
+                    files,
+                    digest,
+                    update_info,
+                    folder: Some(folder),
+                    ..Installed::new(name, Provider::Aur, name)
+                },
+                config.keep_versions(),
+            )
+        })?;
+
+        progress::println(cformat!(
             "<g>Successfully installed <c>{}</c> <g>version <c>{}</c></g>",
             name,
             version
-        );
+        ));
         Ok(())
     }
 
-    pub async fn search(query: &str) -> Result<()> {
+    pub async fn search(query: &str, options: &InstallOptions) -> Result<()> {
         let query = query.trim();
 
         let search_url = format!(
@@ -168,7 +301,7 @@ impl AUR {
                         .default(true)
                         .interact()?
                     {
-                        GITHUB::search(query).await?;
+                        GITHUB::search(query, options).await?;
                     }
                     Ok(())
                 }
@@ -221,7 +354,7 @@ impl AUR {
                             .default(true)
                             .interact()?
                         {
-                            GITHUB::search(query).await?
+                            GITHUB::search(query, options).await?
                         } else {
                             exit(0)
                         };
@@ -243,7 +376,7 @@ impl AUR {
                             .unwrap()
                             .trim();
 
-                        AUR::download(name).await?
+                        AUR::download(name, options).await?
                     };
                     Ok(())
                 }
@@ -254,3 +387,35 @@ impl AUR {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_on_one_line() {
+        let pkgbuild = "pkgname=app\nsha256sums=('abc' \"def\")\n";
+        assert_eq!(AUR::get_array(pkgbuild, "sha256sums"), ["abc", "def"]);
+        assert!(AUR::get_array(pkgbuild, "sha512sums").is_empty());
+    }
+
+    #[test]
+    fn array_over_several_lines() {
+        let pkgbuild = "\
+sha256sums_x86_64=('abc'  # AppImage
+                   'SKIP'
+                   'def')
+sha256sums=('other')
+";
+        assert_eq!(
+            AUR::get_array(pkgbuild, "sha256sums_x86_64"),
+            ["abc", "SKIP", "def"]
+        );
+        assert_eq!(AUR::get_array(pkgbuild, "sha256sums"), ["other"]);
+    }
+
+    #[test]
+    fn empty_array() {
+        assert!(AUR::get_array("sha256sums=()", "sha256sums").is_empty());
+    }
+}
@@ -1,11 +1,30 @@
-use crate::utils::{appimage::AppImage, errors::error, tools::Tools};
+use crate::{
+    api::options::InstallOptions,
+    utils::{
+        appimage::{asset_pattern, matches_pattern, AppImage, UpdateInfo},
+        arch::Arch,
+        checksum::Checksum,
+        config::Config,
+        elf::Elf,
+        errors::error,
+        manifest::{Channel, Installed, Manifest, Provider},
+        progress,
+        staging::Staging,
+        token::github_token,
+        version::{TagFilter, Version},
+        zsync::Zsync,
+    },
+};
 use anyhow::{Context, Result};
 use chrono::{TimeZone, Utc};
 use color_print::{cformat, cprintln};
 use dialoguer::{theme::ColorfulTheme, Select};
 use indicatif::ProgressBar;
-use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
-use semver::Version;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, RANGE, USER_AGENT},
+    StatusCode,
+};
 use serde::{Deserialize, Serialize};
 use std::{process::exit, time::Duration};
 
@@ -16,6 +35,8 @@ pub struct GITHUB {
     pub url: Option<String>,
     pub tag_name: Option<String>,
     pub assets: Option<Vec<Assets>>,
+    pub prerelease: Option<bool>,
+    pub published_at: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -27,7 +48,11 @@ pub struct Items {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Assets {
     pub name: Option<String>,
+    pub size: Option<u64>,
+    /// Api endpoint of the asset, the only one serving assets of private repos
+    pub url: Option<String>,
     pub browser_download_url: Option<String>,
+    pub digest: Option<String>,
 }
 #[derive(Deserialize)]
 struct RateLimit {
@@ -36,13 +61,13 @@ struct RateLimit {
 
 #[derive(Deserialize)]
 struct Rate {
+    limit: u32,
     remaining: u32,
     reset: i64,
 }
 
 impl GITHUB {
-    pub async fn get(url: &str) -> Result<Self> {
-        let client = reqwest::Client::new();
+    fn headers() -> HeaderMap {
         let mut headers = HeaderMap::new();
         headers.insert(USER_AGENT, HeaderValue::from_static("reqwest"));
         headers.insert(
@@ -53,48 +78,208 @@ impl GITHUB {
             "X-GitHub-Api-Version",
             HeaderValue::from_static("2022-11-28"),
         );
-        let response = client.get(url).headers(headers).send().await?;
+        let token = github_token()
+            .and_then(|token| HeaderValue::from_str(&format!("Bearer {}", token)).ok());
+        if let Some(mut token) = token {
+            token.set_sensitive(true);
+            headers.insert(AUTHORIZATION, token);
+        }
+        headers
+    }
+
+    pub async fn get(url: &str) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let response = client.get(url).headers(GITHUB::headers()).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(GITHUB::not_found(url));
+        }
         let response = response.json::<GITHUB>().await?;
         Ok(response)
     }
 
-    async fn get_response(url: &str) -> Result<(String, String)> {
-        let response = GITHUB::get(url).await?;
+    /// Every release of `owner/repo`, newest first
+    pub async fn get_releases(owner: &str, repo: &str) -> Result<Vec<GITHUB>> {
+        let mut releases = Vec::new();
+        GITHUB::scan_releases(owner, repo, |release| {
+            releases.push(release);
+            true
+        })
+        .await?;
+        Ok(releases)
+    }
 
-        let version = response.tag_name.context(error!("No version found"))?;
-        let version = version
-            .chars()
-            .filter(|c| c.is_ascii_digit() || *c == '.')
-            .collect::<String>();
+    /// Hands the releases of `owner/repo` to `visit`, newest first, until it
+    /// returns `false`. Pages are only fetched as far as the scan goes
+    pub async fn scan_releases(
+        owner: &str,
+        repo: &str,
+        mut visit: impl FnMut(GITHUB) -> bool,
+    ) -> Result<()> {
+        let client = reqwest::Client::new();
+        let per_page = 100;
+        for page in 1.. {
+            let url = format!(
+                "https://api.github.com/repos/{}/{}/releases?per_page={}&page={}",
+                owner, repo, per_page, page
+            );
+            let response = client.get(&url).headers(GITHUB::headers()).send().await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Err(GITHUB::not_found(&format!("{}/{}", owner, repo)));
+            }
+            if !response.status().is_success() {
+                return Err(error!(format!(
+                    "Failed to list releases of {}/{}",
+                    owner, repo
+                )));
+            }
+            let page = response.json::<Vec<GITHUB>>().await?;
+            let last = page.len() < per_page;
+            for release in page {
+                if !visit(release) {
+                    return Ok(());
+                }
+            }
+            if last {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Github answers 404 rather than 403 for private repos it is not
+    /// allowed to show
+    fn not_found(what: &str) -> anyhow::Error {
+        let hint = match github_token() {
+            Some(_) => ", check that the token can read it",
+            None => ", set $GITHUB_TOKEN if it is private",
+        };
+        error!(format!("{} was not found on github{}", what, hint))
+    }
+
+    /// Where to download `asset` from and the headers to send. With a token
+    /// assets go through the api, which redirects to the file and serves
+    /// assets of private repos too
+    fn asset_source(asset: &Assets) -> Option<(String, HeaderMap)> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("reqwest"));
+        if let (Some(_), Some(url)) = (github_token(), &asset.url) {
+            let mut headers = GITHUB::headers();
+            headers.insert(ACCEPT, HeaderValue::from_static("application/octet-stream"));
+            return Some((url.clone(), headers));
+        }
+        Some((asset.browser_download_url.clone()?, headers))
+    }
+
+    async fn get_text(asset: &Assets) -> Result<String> {
+        let client = reqwest::Client::new();
+        let (url, headers) =
+            GITHUB::asset_source(asset).context(error!("No URL to checksum file found"))?;
+        let response = client.get(&url).headers(headers).send().await?;
+        if !response.status().is_success() {
+            return Err(error!(format!("Failed to fetch {}", url)));
+        }
+        Ok(response.text().await?)
+    }
+
+    /// Looks for the checksum of `asset` in its `digest` field and then in
+    /// checksum files published alongside it in the same release
+    async fn get_checksum(asset: &Assets, assets: &[Assets]) -> Result<Option<Checksum>> {
+        if let Some(checksum) = asset.digest.as_deref().and_then(Checksum::from_digest) {
+            return Ok(Some(checksum));
+        }
+
+        let asset_name = asset.name.as_deref().unwrap_or_default();
+        let specific = [
+            format!("{}.sha256", asset_name.to_lowercase()),
+            format!("{}.sha512", asset_name.to_lowercase()),
+        ];
+        // `SHA256SUMS`, `checksums.txt` and the like, not their signatures
+        let is_sums = |name: &str| {
+            name.contains("sums") && !name.ends_with(".asc") && !name.ends_with(".sig")
+        };
 
-        let assets = response.assets.unwrap();
+        let mut candidates: Vec<&Assets> = assets
+            .iter()
+            .filter(|a| {
+                let name = a.name.as_deref().unwrap_or_default().to_lowercase();
+                specific.contains(&name) || is_sums(&name)
+            })
+            .collect();
+        candidates.sort_by_key(|a| {
+            let name = a.name.as_deref().unwrap_or_default().to_lowercase();
+            !specific.contains(&name)
+        });
+
+        for candidate in candidates {
+            // Another checksum file may still have it
+            let Ok(content) = GITHUB::get_text(candidate).await else {
+                continue;
+            };
+            let sums_name = candidate.name.as_deref().unwrap_or_default();
+            if let Some(checksum) = Checksum::from_sums(&content, sums_name, asset_name) {
+                return Ok(Some(checksum));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Picks the AppImage asset of a release for `name`, only assets matching
+    /// the pattern of `update_info` are considered when it is given. Asks
+    /// which one to install unless a single asset matches `pattern`, or fails
+    /// when `noprompt` is set
+    async fn get_response(
+        name: &str,
+        response: GITHUB,
+        update_info: Option<&UpdateInfo>,
+        pattern: Option<&str>,
+        noprompt: bool,
+    ) -> Result<(Assets, String, Option<Checksum>)> {
+        let tag = response.tag_name.context(error!("No version found"))?;
+
+        let assets = response.assets.unwrap_or_default();
         let appimage_assets: Vec<_> = assets
             .iter()
             .filter(|a| {
-                a.name
-                    .as_ref()
-                    .unwrap()
-                    .to_lowercase()
-                    .ends_with(".appimage")
+                let name = a.name.as_deref().unwrap_or_default();
+                match update_info {
+                    Some(update_info) => update_info.matches(name),
+                    None => name.to_lowercase().ends_with(".appimage"),
+                }
             })
             .collect();
 
         if appimage_assets.is_empty() {
             return Err(error!("No AppImage found"));
-        } else if appimage_assets.len() == 1 {
-            let asset = appimage_assets[0];
-            let appimage_url = asset
-                .browser_download_url
-                .as_ref()
-                .context(error!("No URL to AppImage found"))?
-                .to_string();
-            return Ok((appimage_url, version));
+        }
+        let mut appimage_assets = GITHUB::for_host(appimage_assets).await?;
+        if let Some(pattern) = pattern {
+            let matching: Vec<&Assets> = appimage_assets
+                .iter()
+                .filter(|a| matches_pattern(pattern, a.name.as_deref().unwrap_or_default()))
+                .copied()
+                .collect();
+            // A pattern matching nothing is stale, all assets are offered then
+            if !matching.is_empty() {
+                appimage_assets = matching;
+            }
+        }
+        if appimage_assets.len() == 1 {
+            let asset = appimage_assets[0].clone();
+            let checksum = GITHUB::get_checksum(&asset, &assets).await?;
+            return Ok((asset, tag, checksum));
         }
 
         let items: Vec<&str> = appimage_assets
             .iter()
             .map(|a| a.name.as_ref().unwrap().as_str())
             .collect();
+        if noprompt {
+            return Err(error!(format!(
+                "Several AppImages found ({}), pick one with `appi asset {} <glob>`",
+                items.join(", "),
+                name
+            )));
+        }
         let selection = Select::with_theme(&ColorfulTheme::default())
             .with_prompt(cformat!("<y>multiple appimages found, please select one:"))
             .default(0)
@@ -104,60 +289,261 @@ impl GITHUB {
 
         match selection {
             Some(index) => {
-                let asset = appimage_assets[index];
-                let appimage_url = asset
-                    .browser_download_url
-                    .as_ref()
-                    .context(error!("No URL to AppImage found"))?
-                    .to_string();
-                Ok((appimage_url, version))
+                let asset = appimage_assets[index].clone();
+                let checksum = GITHUB::get_checksum(&asset, &assets).await?;
+                Ok((asset, tag, checksum))
             }
             None => exit(0),
         }
     }
 
-    pub async fn get_latest_version(name: &str, creator: &str) -> Result<Version> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/latest",
-            creator, name
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(USER_AGENT, "reqwest".parse().unwrap());
+    /// Keeps the assets built for this machine, judged by the architecture
+    /// named in them and, when several name none, by their ELF header
+    async fn for_host(assets: Vec<&Assets>) -> Result<Vec<&Assets>> {
+        let Some(host) = Arch::host() else {
+            return Ok(assets);
+        };
+        let arches: Vec<Option<Arch>> = assets
+            .iter()
+            .map(|a| Arch::from_name(a.name.as_deref().unwrap_or_default()))
+            .collect();
+        let matching: Vec<&Assets> = assets
+            .iter()
+            .zip(&arches)
+            .filter(|(_, arch)| **arch == Some(host))
+            .map(|(a, _)| *a)
+            .collect();
+        if !matching.is_empty() {
+            return Ok(matching);
+        }
+
+        let unnamed: Vec<&Assets> = assets
+            .iter()
+            .zip(&arches)
+            .filter(|(_, arch)| arch.is_none())
+            .map(|(a, _)| *a)
+            .collect();
+        let mut others: Vec<Arch> = arches.iter().flatten().copied().collect();
+        let mut candidates = Vec::new();
+        if unnamed.len() == 1 {
+            candidates = unnamed;
+        } else {
+            for asset in unnamed {
+                match GITHUB::get_machine(asset)
+                    .await
+                    .and_then(Arch::from_machine)
+                {
+                    Some(arch) if arch != host => others.push(arch),
+                    _ => candidates.push(asset),
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            let mut others: Vec<String> = others.iter().map(Arch::to_string).collect();
+            others.sort();
+            others.dedup();
+            return Err(error!(format!(
+                "No AppImage for {} found, only for {}",
+                host,
+                others.join(", ")
+            )));
+        }
+        Ok(candidates)
+    }
 
-        let release = GITHUB::get(&url).await?;
-        let tag_name = release.tag_name.unwrap_or_default();
-        let version_string = tag_name.trim_start_matches('v');
-        let latest_version = Version::parse(version_string)?;
-        Ok(latest_version)
+    /// Reads the ELF machine of an asset from its first bytes
+    async fn get_machine(asset: &Assets) -> Option<u16> {
+        let (url, headers) = GITHUB::asset_source(asset)?;
+        let response = reqwest::Client::new()
+            .get(url)
+            .headers(headers)
+            .header(RANGE, "bytes=0-63")
+            .send()
+            .await
+            .ok()?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return None;
+        }
+        Elf::machine(&response.bytes().await.ok()?)
+    }
+
+    /// The release an AppImage's update information points at
+    pub fn release_url(owner: &str, repo: &str, release: &str) -> String {
+        match release {
+            "latest" => format!(
+                "https://api.github.com/repos/{}/{}/releases/latest",
+                owner, repo
+            ),
+            tag => format!(
+                "https://api.github.com/repos/{}/{}/releases/tags/{}",
+                owner, repo, tag
+            ),
+        }
+    }
+
+    /// Whether this release belongs to `channel`
+    pub fn in_channel(&self, channel: &Channel) -> bool {
+        let tag = self.tag_name.as_deref().unwrap_or_default();
+        match channel {
+            Channel::Stable => !self.prerelease.unwrap_or(false),
+            Channel::Prerelease => true,
+            Channel::Nightly => {
+                let tag = tag.to_lowercase();
+                tag.contains("nightly") || tag.contains("continuous")
+            }
+            Channel::Tag(pattern) => Regex::new(pattern).is_ok_and(|regex| regex.is_match(tag)),
+        }
+    }
+
+    /// Whether the tag of this release passes `filter`, releases of other
+    /// products in the same repository do not
+    pub fn matches_filter(&self, filter: Option<&TagFilter>) -> bool {
+        let tag = self.tag_name.as_deref().unwrap_or_default();
+        filter.is_none_or(|filter| filter.matches(tag))
+    }
+
+    /// Whether this release has an AppImage, one matching the pattern of
+    /// `update_info` when it is given
+    pub fn has_appimage(&self, update_info: Option<&UpdateInfo>) -> bool {
+        self.assets.iter().flatten().any(|asset| {
+            let name = asset.name.as_deref().unwrap_or_default();
+            match update_info {
+                Some(update_info) => update_info.matches(name),
+                None => name.to_lowercase().ends_with(".appimage"),
+            }
+        })
+    }
+
+    /// The newest release of `owner/repo` in `channel` passing `filter` that
+    /// has an AppImage, looking through up to `release_scan` of them when the
+    /// newest one was published without. Github's latest release is asked for
+    /// directly when it is the one wanted
+    pub async fn get_appimage_release(
+        owner: &str,
+        repo: &str,
+        channel: &Channel,
+        filter: Option<&TagFilter>,
+        update_info: Option<&UpdateInfo>,
+    ) -> Result<Self> {
+        if channel.is_stable() && filter.is_none() {
+            let latest = GITHUB::get(&GITHUB::release_url(owner, repo, "latest")).await;
+            if let Ok(release) = latest {
+                if release.has_appimage(update_info) {
+                    return Ok(release);
+                }
+            }
+        }
+
+        let scan = Config::load()?.release_scan();
+        let mut seen = 0;
+        let mut found = None;
+        GITHUB::scan_releases(owner, repo, |release| {
+            if release.tag_name.is_none()
+                || !release.in_channel(channel)
+                || !release.matches_filter(filter)
+            {
+                return true;
+            }
+            if release.has_appimage(update_info) {
+                found = Some(release);
+                return false;
+            }
+            seen += 1;
+            seen < scan
+        })
+        .await?;
+
+        match found {
+            Some(release) => Ok(release),
+            None if seen == 0 => Err(error!(format!(
+                "No {} release of {}/{} found",
+                channel, owner, repo
+            ))),
+            None => Err(error!(format!(
+                "No AppImage found in the last {} releases of {}/{}",
+                seen, owner, repo
+            ))),
+        }
+    }
+
+    /// The day a release was published, from its `published_at` timestamp
+    pub fn published(&self) -> Option<String> {
+        let date = self.published_at.as_deref()?.split('T').next()?;
+        Some(date.to_string())
+    }
+
+    /// Tag of the release declared by `update_info` and the day it was published
+    pub async fn get_release_tag(
+        update_info: &UpdateInfo,
+        filter: Option<&TagFilter>,
+    ) -> Result<(String, Option<String>)> {
+        let UpdateInfo::GhReleases {
+            owner,
+            repo,
+            release,
+            ..
+        } = update_info
+        else {
+            return Err(error!("Not a github update source"));
+        };
+        let response = match release.as_str() {
+            "latest" => {
+                GITHUB::get_appimage_release(
+                    owner,
+                    repo,
+                    &Channel::Stable,
+                    filter,
+                    Some(update_info),
+                )
+                .await?
+            }
+            tag => GITHUB::get(&GITHUB::release_url(owner, repo, tag)).await?,
+        };
+        let published = response.published();
+        let tag = response.tag_name.context(error!("No version found"))?;
+        Ok((tag, published))
+    }
+
+    /// Latest version of `creator/name` passing `filter` and the day it was
+    /// published
+    pub async fn get_latest_version(
+        name: &str,
+        creator: &str,
+        filter: Option<&TagFilter>,
+    ) -> Result<(Version, Option<String>)> {
+        let release =
+            GITHUB::get_appimage_release(creator, name, &Channel::Stable, filter, None).await?;
+        let published = release.published();
+        let tag_name = release.tag_name.context(error!("No version found"))?;
+        Ok((Version::from_tag(&tag_name, filter), published))
     }
 
     pub async fn check_rate_limit() -> Result<()> {
         let client = reqwest::Client::new();
-        let mut headers = HeaderMap::new();
-        headers.insert(USER_AGENT, HeaderValue::from_static("reqwest"));
-        headers.insert(
-            "Accept",
-            HeaderValue::from_static("application/vnd.github+json"),
-        );
-        headers.insert(
-            "X-GitHub-Api-Version",
-            HeaderValue::from_static("2022-11-28"),
-        );
         let response = client
             .get("https://api.github.com/rate_limit")
-            .headers(headers)
+            .headers(GITHUB::headers())
             .send()
-            .await?
-            .json::<RateLimit>()
             .await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(error!(
+                "Github rejected the token, check $GITHUB_TOKEN, $GH_TOKEN or github_token in the config"
+            ));
+        }
+        let response = response.json::<RateLimit>().await?;
 
         if response.rate.remaining == 0 {
             let reset_time = Utc.timestamp_opt(response.rate.reset as i64, 0).unwrap();
             let remaining_time = reset_time.signed_duration_since(Utc::now());
             let remaining_minutes = remaining_time.num_minutes();
+            let hint = match github_token() {
+                Some(_) => "",
+                None => ", or set $GITHUB_TOKEN to raise the limit",
+            };
             return Err(error!(format!(
-                "Github rate limit exceeded. Wait for {} min and try again",
-                remaining_minutes
+                "Github rate limit of {} requests per hour exceeded. Wait for {} min and try again{}",
+                response.rate.limit, remaining_minutes, hint
             )));
         }
 
@@ -165,82 +551,166 @@ impl GITHUB {
     }
 
     async fn check_appimage(full_name: &str) -> Result<bool> {
-        let url = format!("https://api.github.com/repos/{}/releases/latest", full_name);
-        let response = GITHUB::get(&url).await?;
-
-        let assets = match response.assets {
-            Some(assets) => assets,
-            None => {
-                return Ok(false);
-            }
+        let Some((owner, repo)) = full_name.split_once('/') else {
+            return Ok(false);
         };
-        if assets.iter().any(|a| {
-            a.name
-                .as_ref()
-                .unwrap()
-                .to_lowercase()
-                .ends_with(".appimage")
-        }) {
-            return Ok(true);
-        }
-
-        Ok(false)
+        let release = GITHUB::get_appimage_release(owner, repo, &Channel::Stable, None, None).await;
+        Ok(release.is_ok())
     }
 
-    pub async fn download(repo_url: &str) -> Result<()> {
+    /// Installs `owner/repo`, or the release `tag` of it with `owner/repo@tag`
+    pub async fn download(repo_url: &str, options: &InstallOptions) -> Result<()> {
         GITHUB::check_rate_limit().await?;
+        let (repo_url, options) = match repo_url.split_once('@') {
+            Some((repo_url, tag)) => (
+                repo_url,
+                InstallOptions {
+                    tag: Some(tag.to_string()),
+                    ..options.clone()
+                },
+            ),
+            None => (repo_url, options.clone()),
+        };
         let repo_url = repo_url.trim_end_matches('/');
         let repo_parts: Vec<&str> = repo_url.split('/').collect();
+        if repo_parts.len() < 2 {
+            return Err(error!("Expected a repository as owner/repo"));
+        }
         let owner = repo_parts[repo_parts.len() - 2].to_string();
         let repo = repo_parts[repo_parts.len() - 1].to_string();
-        let owner_name = owner.replace('-', "_");
-        let repo_name = repo.replace('-', "_");
 
-        let app_folder = format!("/home/{}/Applications/{}", Tools.get_user()?, repo_name);
-        let app_folder_path = std::path::Path::new(&app_folder);
-        if app_folder_path.exists() {
-            cprintln!("<c>{} <y>is already installed", repo_name);
+        let manifest = Manifest::load()?;
+        if manifest.get(&repo).is_some() {
+            cprintln!("<c>{} <y>is already installed", repo);
             return Ok(());
         }
 
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/latest",
-            owner, repo
-        );
-
-        let (appimage_url, version) = GITHUB::get_response(&url).await?;
-
-        let file_path = format!(
-            "/home/{}/Applications/{}/{}-{}-v{}.appimage",
-            Tools.get_user()?,
-            repo_name,
-            repo_name,
-            owner_name,
-            version
-        );
-
-        let pb = ProgressBar::new_spinner();
-        pb.enable_steady_tick(Duration::from_millis(120));
-        pb.set_message(cformat!("<c>Downloading {}...", repo_name));
-        AppImage.download(&appimage_url, &file_path).await?;
-        pb.finish_and_clear();
+        GITHUB::install(&repo, &owner, &repo, None, &options).await
+    }
 
-        let pb = ProgressBar::new_spinner();
-        pb.enable_steady_tick(Duration::from_millis(120));
-        pb.set_message(cformat!("<c>Installing {}...", repo_name));
-        AppImage.extract(&file_path)?;
-        AppImage.integrate(&file_path, &repo_name)?;
-        pb.finish_and_clear();
+    /// Installs `owner/repo` as `name`, following the AppImage's own update
+    /// information when it is known. `options.tag` selects a release other
+    /// than the latest one, a channel other than stable follows the newest
+    /// release in it
+    pub async fn install(
+        name: &str,
+        owner: &str,
+        repo: &str,
+        update_info: Option<&UpdateInfo>,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        let manifest = Manifest::load()?;
+        let channel = options
+            .channel
+            .clone()
+            .or_else(|| manifest.get(name).map(|app| app.channel.clone()))
+            .unwrap_or_default();
+        let filter = options
+            .tag_filter
+            .clone()
+            .or_else(|| manifest.get(name).and_then(|app| app.tag_filter.clone()));
+        let response = match (&options.tag, update_info) {
+            (Some(tag), _) => GITHUB::get(&GITHUB::release_url(owner, repo, tag)).await?,
+            (None, Some(UpdateInfo::GhReleases { release, .. }))
+                if release != "latest" && channel.is_stable() =>
+            {
+                GITHUB::get(&GITHUB::release_url(owner, repo, release)).await?
+            }
+            _ => {
+                GITHUB::get_appimage_release(owner, repo, &channel, filter.as_ref(), update_info)
+                    .await?
+            }
+        };
 
-        cprintln!(
+        // The asset picked last time is picked again without asking
+        let pattern = options
+            .asset
+            .clone()
+            .or_else(|| manifest.get(name).and_then(|app| app.asset_pattern.clone()));
+        let (asset, tag, checksum) = GITHUB::get_response(
+            name,
+            response,
+            update_info,
+            pattern.as_deref(),
+            options.noprompt,
+        )
+        .await?;
+        // Kept as published, versions are compared with `Version`. Tags of
+        // monorepos only show the part the filter extracts
+        let version = filter
+            .as_ref()
+            .and_then(|filter| filter.version(&tag))
+            .unwrap_or(&tag)
+            .to_string();
+        let (download_url, headers) =
+            GITHUB::asset_source(&asset).context(error!("No URL to AppImage found"))?;
+        let asset_name = asset.name.context(error!("No AppImage found"))?;
+        let pattern = pattern
+            .filter(|pattern| matches_pattern(pattern, &asset_name))
+            .unwrap_or_else(|| asset_pattern(&asset_name, &tag));
+        let appimage_url = asset
+            .browser_download_url
+            .context(error!("No URL to AppImage found"))?;
+
+        let config = Config::load()?;
+        let staging = Staging::new(&config, name, &tag)?;
+        let file_path = staging.file_path();
+
+        let downloaded = async {
+            let zsync = match options.seed {
+                Some(_) => Zsync::find(&appimage_url).await,
+                None => None,
+            };
+            AppImage
+                .download_delta(
+                    &download_url,
+                    headers,
+                    &file_path,
+                    name,
+                    zsync.as_ref(),
+                    options.seed.as_deref(),
+                )
+                .await?;
+            Ok(checksum)
+        }
+        .await;
+        let folder = staging.version_folder();
+        let (files, digest, embedded) = AppImage
+            .install_staged(staging, downloaded, options.skip_verify, &config)
+            .await?;
+        let update_info = embedded.or(update_info.cloned());
+
+        Manifest::update(|manifest| {
+            manifest.insert_version(
+                Installed {
+                    owner: Some(owner.to_string()),
+                    tag,
+                    version: version.clone(),
+                    asset: asset_name,
+                    url: appimage_url,
+                    files,
+                    digest,
+                    update_info,
+                    folder: Some(folder),
+                    chosen: options.tag.is_some(),
+                    asset_pattern: Some(pattern),
+                    channel,
+                    tag_filter: filter,
+                    ..Installed::new(name, Provider::Github, repo)
+                },
+                config.keep_versions(),
+            )
+        })?;
+
+        progress::println(cformat!(
             "<g>Successfully installed <c>{}</c> <g>version <c>{}</c></g>",
-            repo_name,
+            name,
             version
-        );
+        ));
         Ok(())
     }
 
-    pub async fn search(query: &str) -> Result<()> {
+    pub async fn search(query: &str, options: &InstallOptions) -> Result<()> {
         GITHUB::check_rate_limit().await?;
         let query = query.trim();
 
@@ -313,6 +783,7 @@ impl GITHUB {
                                 .ok_or(error!("Failed to split selection"))
                                 .unwrap()
                                 .trim(),
+                            options,
                         )
                         .await?
                     }
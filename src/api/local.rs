@@ -0,0 +1,198 @@
+use crate::{
+    api::options::InstallOptions,
+    utils::{
+        appimage::{AppImage, UpdateInfo},
+        arch::Arch,
+        config::Config,
+        manifest::{Installed, Manifest, Provider},
+        progress,
+        staging::Staging,
+        zsync::Zsync,
+    },
+};
+use anyhow::Result;
+use color_print::{cformat, cprintln};
+use reqwest::header::HeaderMap;
+use std::{
+    fs::{self, Permissions},
+    os::unix::prelude::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// AppImages that do not come from a provider: files on disk and the
+/// zsync urls they declare in their update information
+pub struct LOCAL;
+
+impl LOCAL {
+    /// Name used for an AppImage file such as `Obsidian-1.5.3-x86_64.AppImage`,
+    /// everything from its version or architecture on is dropped
+    fn name_of(path: &Path) -> String {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut end = stem.len();
+        let mut start = 0;
+        for word in stem.split(['-', '_']) {
+            let version = word
+                .trim_start_matches(['v', 'V'])
+                .starts_with(|c: char| c.is_ascii_digit());
+            if start > 0 && (version || Arch::from_name(word).is_some()) {
+                end = start;
+                break;
+            }
+            start += word.len() + 1;
+        }
+        let name = stem[..end].trim_end_matches(['-', '_', '.']);
+        match name.is_empty() {
+            true => stem.to_lowercase(),
+            false => name.to_lowercase(),
+        }
+    }
+
+    pub async fn install(path: &str) -> Result<()> {
+        let source = Path::new(path).canonicalize()?;
+        let name = LOCAL::name_of(&source);
+
+        let manifest = Manifest::load()?;
+        if manifest.get(&name).is_some() {
+            cprintln!("<c>{} <y>is already installed", name);
+            return Ok(());
+        }
+
+        let asset = source
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let version = LOCAL::version_of(&source.to_string_lossy(), &asset)?;
+
+        let config = Config::load()?;
+        let mut staging = Staging::new(&config, &name, &version)?;
+        let file_path = staging.file_path();
+
+        let installed = (|| {
+            fs::copy(&source, &file_path)?;
+            fs::set_permissions(&file_path, Permissions::from_mode(0o755))?;
+            AppImage.install(&mut staging, None, false, &config)
+        })();
+        let folder = staging.version_folder();
+        let installed = staging.finish(installed)?;
+
+        let url = source.to_string_lossy();
+        LOCAL::record(&name, installed, &version, folder, &url, &asset)
+    }
+
+    /// Installs the file a `.zsync` control file points at as `name`
+    pub async fn download(name: &str, zsync_url: &str, options: &InstallOptions) -> Result<()> {
+        let zsync = Zsync::fetch(zsync_url).await?;
+
+        let config = Config::load()?;
+        let mut staging = Staging::new(&config, name, "")?;
+        let file_path = staging.file_path();
+
+        let downloaded = async {
+            AppImage
+                .download_delta(
+                    &zsync.url,
+                    HeaderMap::new(),
+                    &file_path,
+                    name,
+                    Some(&zsync),
+                    options.seed.as_deref(),
+                )
+                .await?;
+            staging.version = LOCAL::version_of(&file_path, &zsync.filename)?;
+            Ok(zsync.sha1.clone())
+        }
+        .await;
+        let version = staging.version.clone();
+        let folder = staging.version_folder();
+        let installed = AppImage
+            .install_staged(staging, downloaded, options.skip_verify, &config)
+            .await?;
+
+        LOCAL::record(
+            name,
+            installed,
+            &version,
+            folder,
+            &zsync.url,
+            &zsync.filename,
+        )
+    }
+
+    /// `X-AppImage-Version` of the desktop entry, or the file name when the
+    /// AppImage does not declare one
+    fn version_of(file_path: &str, asset: &str) -> Result<String> {
+        Ok(AppImage.version(file_path)?.unwrap_or_else(|| {
+            Path::new(asset)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        }))
+    }
+
+    fn record(
+        name: &str,
+        (files, digest, update_info): (Vec<PathBuf>, String, Option<UpdateInfo>),
+        version: &str,
+        folder: PathBuf,
+        url: &str,
+        asset: &str,
+    ) -> Result<()> {
+        let config = Config::load()?;
+
+        if update_info.is_none() {
+            progress::println(cformat!(
+                "<y>{} declares no update information, it will not be updated",
+                name
+            ));
+        }
+
+        Manifest::update(|manifest| {
+            manifest.insert_version(
+                Installed {
+                    tag: version.to_string(),
+                    version: version.to_string(),
+                    asset: asset.to_string(),
+                    url: url.to_string(),
+                    files,
+                    digest,
+                    update_info,
+                    folder: Some(folder),
+                    ..Installed::new(name, Provider::Local, name)
+                },
+                config.keep_versions(),
+            )
+        })?;
+
+        progress::println(cformat!(
+            "<g>Successfully installed <c>{}</c> <g>version <c>{}</c></g>",
+            name,
+            version
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_drops_version_and_arch() {
+        for (file, name) in [
+            ("Obsidian-1.5.3-x86_64.AppImage", "obsidian"),
+            ("balena_etcher-1.0.AppImage", "balena_etcher"),
+            (
+                "Visual-Studio-Code-1.86.0-x86_64.AppImage",
+                "visual-studio-code",
+            ),
+            ("appimagetool-x86_64.AppImage", "appimagetool"),
+            ("Bitwarden-v2024.1.0.AppImage", "bitwarden"),
+            ("nvim.appimage", "nvim"),
+            ("7zip.AppImage", "7zip"),
+        ] {
+            assert_eq!(LOCAL::name_of(Path::new(file)), name);
+        }
+    }
+}
@@ -1,2 +1,4 @@
 pub mod aur;
 pub mod github;
+pub mod local;
+pub mod options;
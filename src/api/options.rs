@@ -0,0 +1,34 @@
+use crate::utils::{manifest::Channel, version::TagFilter};
+use std::path::PathBuf;
+
+/// Settings chosen on the command line that apply to a single install
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InstallOptions {
+    /// Install even when the download does not match its published checksum
+    pub skip_verify: bool,
+    /// Previous version of the AppImage, reused by zsync delta updates
+    pub seed: Option<PathBuf>,
+    /// Release to install instead of the latest one
+    pub tag: Option<String>,
+    /// Glob picking the asset when a release has several AppImages
+    pub asset: Option<String>,
+    /// Releases to follow instead of the ones recorded for the app
+    pub channel: Option<Channel>,
+    /// Releases of one product in a repository releasing several
+    pub tag_filter: Option<TagFilter>,
+    /// Fail instead of asking, for installs running next to others
+    pub noprompt: bool,
+}
+
+/// Which apps `update` looks at and how it asks before installing
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpdateOptions {
+    /// Apps to update, every installed app when empty
+    pub names: Vec<String>,
+    /// Apps left alone
+    pub exclude: Vec<String>,
+    /// Pick the apps to update from the outdated ones
+    pub interactive: bool,
+    /// Install without asking for confirmation
+    pub noconfirm: bool,
+}
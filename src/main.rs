@@ -1,13 +1,34 @@
 use anyhow::Result;
 use appi::{
-    api::{aur::AUR, github::GITHUB},
-    modules::{delete::delete, list::list, update::update},
-    utils::completions::Completions,
+    api::{
+        aur::AUR,
+        github::GITHUB,
+        local::LOCAL,
+        options::{InstallOptions, UpdateOptions},
+    },
+    modules::{
+        asset::asset,
+        config::{config_get, config_list, config_set},
+        delete::delete,
+        downgrade::downgrade,
+        list::list,
+        outdated::{outdated, UPDATES_AVAILABLE},
+        pin::{pin, unpin},
+        rollback::rollback,
+        update::update,
+    },
+    utils::{
+        completions::Completions,
+        config::Config,
+        interrupt,
+        manifest::{Channel, Provider},
+        version::TagFilter,
+    },
 };
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::Shell;
 use color_print::cprintln;
-use std::process::exit;
+use std::{path::Path, process::exit};
 
 #[derive(Parser, Debug, PartialEq)]
 #[command(author, version, about, long_about = None)]
@@ -28,27 +49,120 @@ enum Commands {
         args: Option<String>,
         #[arg(short = 'g', long = "github")]
         github: bool,
+        #[arg(short = 'a', long = "aur", conflicts_with = "github")]
+        aur: bool,
+        /// Install even if the checksum does not match
+        #[arg(long = "skip-verify")]
+        skip_verify: bool,
     },
 
-    /// Install an AppImage
+    /// Install an AppImage by name, as owner/repo@tag for a specific release, or from a file on disk
     #[clap(short_flag = 'i')]
     Install {
         args: Option<String>,
         #[arg(short = 'g', long = "github")]
         github: bool,
+        #[arg(short = 'a', long = "aur", conflicts_with = "github")]
+        aur: bool,
+        /// Install even if the checksum does not match
+        #[arg(long = "skip-verify")]
+        skip_verify: bool,
+        /// Pick the asset matching this glob when a release has several AppImages
+        #[arg(long = "asset")]
+        asset: Option<String>,
+        /// Releases to follow: stable, prerelease, nightly or tag:<regex>
+        #[arg(long = "channel")]
+        channel: Option<Channel>,
+        /// Only follow releases tagged with this prefix, for repos releasing several products
+        #[arg(long = "tag-prefix")]
+        tag_prefix: Option<String>,
+        /// Only follow releases whose tag matches this regex, its first group being the version
+        #[arg(long = "tag-regex", conflicts_with = "tag_prefix")]
+        tag_regex: Option<TagFilter>,
     },
-    /// Update all installed AppImages
+    /// Update all installed AppImages, or only the ones named
     #[clap(short_flag = 'u')]
-    Update,
+    Update {
+        names: Vec<String>,
+        /// Leave these AppImages alone
+        #[arg(short = 'e', long = "exclude", value_delimiter = ',')]
+        exclude: Vec<String>,
+        /// Pick the AppImages to update from the outdated ones
+        #[arg(short = 'i', long = "interactive")]
+        interactive: bool,
+        /// Do not ask for confirmation before installing updates
+        #[arg(long = "noconfirm")]
+        noconfirm: bool,
+        /// Install even if the checksum does not match
+        #[arg(long = "skip-verify")]
+        skip_verify: bool,
+        /// Only show what would be updated, like `outdated`
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Show installed and available versions, exits with 100 when updates exist
+    Outdated,
 
     /// Delete an AppImage
     #[clap(short_flag = 'd')]
     Delete,
+
+    /// Install another release of an AppImage, picked from a list when no tag is given
+    Downgrade {
+        name: String,
+        tag: Option<String>,
+        /// Install even if the checksum does not match
+        #[arg(long = "skip-verify")]
+        skip_verify: bool,
+    },
+
+    /// Switch an AppImage back to its previous version
+    Rollback { name: String },
+
+    /// Skip an AppImage during update, or only accept versions matching a requirement such as ~1.4
+    Pin {
+        name: String,
+        requirement: Option<String>,
+    },
+
+    /// Let update move an AppImage to its latest version again
+    Unpin { name: String },
+
+    /// Show or change the glob picking an AppImage's asset when a release has several
+    Asset {
+        name: String,
+        pattern: Option<String>,
+    },
+
+    /// Show or change settings
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum ConfigAction {
+    /// Print the value of a setting
+    Get { key: String },
+    /// Change the value of a setting
+    Set { key: String, value: String },
+    /// Print every setting
+    List,
+}
+
+fn use_github(github: bool, aur: bool) -> Result<bool> {
+    if github || aur {
+        return Ok(github);
+    }
+    Ok(Config::load()?.provider() == Provider::Github)
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     cprintln!("\n<r,s>❱❱</> <b,s>APPI</> <r>ᐅ</>\n");
+    interrupt::listen();
     let cli = Cli::parse();
     if let Some(generator) = cli.generator {
         let mut cmd = Cli::command();
@@ -62,37 +176,127 @@ async fn main() -> Result<()> {
         }
     }
     match &cli.commands {
-        Some(Commands::Search { args, github }) => {
-            if *github {
-                GITHUB::search(args.as_ref().unwrap()).await?;
+        Some(Commands::Search {
+            args,
+            github,
+            aur,
+            skip_verify,
+        }) => {
+            let options = InstallOptions {
+                skip_verify: *skip_verify,
+                ..Default::default()
+            };
+            if args.is_some() && use_github(*github, *aur)? {
+                GITHUB::search(args.as_ref().unwrap(), &options).await?;
                 exit(0)
             } else if args.is_some() {
-                AUR::search(args.as_ref().unwrap()).await?;
+                AUR::search(args.as_ref().unwrap(), &options).await?;
                 exit(0)
             } else {
                 cprintln!("<r>Missing arguments</r>");
                 exit(1)
             }
         }
-        Some(Commands::Install { args, github }) => {
-            if *github {
-                GITHUB::download(args.as_ref().unwrap()).await?;
+        Some(Commands::Install {
+            args,
+            github,
+            aur,
+            skip_verify,
+            asset,
+            channel,
+            tag_prefix,
+            tag_regex,
+        }) => {
+            let options = InstallOptions {
+                skip_verify: *skip_verify,
+                asset: asset.clone(),
+                channel: channel.clone(),
+                tag_filter: tag_prefix
+                    .as_deref()
+                    .map(TagFilter::prefix)
+                    .or_else(|| tag_regex.clone()),
+                ..Default::default()
+            };
+            if args
+                .as_deref()
+                .is_some_and(|path| Path::new(path).is_file())
+            {
+                LOCAL::install(args.as_ref().unwrap()).await?;
+                exit(0)
+            } else if args.is_some() && use_github(*github, *aur)? {
+                GITHUB::download(args.as_ref().unwrap(), &options).await?;
                 exit(0)
             } else if args.is_some() {
                 let name = &args.as_ref().unwrap();
-                AUR::download(name).await?;
+                AUR::download(name, &options).await?;
                 exit(0)
             } else {
                 cprintln!("<r>Missing arguments</r>");
                 exit(1)
             }
         }
-        Some(Commands::Update) => {
-            update().await?;
+        Some(Commands::Update {
+            names,
+            exclude,
+            interactive,
+            noconfirm,
+            skip_verify,
+            dry_run,
+        }) => {
+            if *dry_run {
+                if outdated(names, exclude).await? > 0 {
+                    exit(UPDATES_AVAILABLE)
+                }
+            } else {
+                let options = InstallOptions {
+                    skip_verify: *skip_verify,
+                    ..Default::default()
+                };
+                let selection = UpdateOptions {
+                    names: names.clone(),
+                    exclude: exclude.clone(),
+                    interactive: *interactive,
+                    noconfirm: *noconfirm,
+                };
+                update(&options, &selection).await?;
+            }
+        }
+        Some(Commands::Outdated) => {
+            if outdated(&[], &[]).await? > 0 {
+                exit(UPDATES_AVAILABLE)
+            }
         }
         Some(Commands::Delete) => {
             delete().await?;
         }
+        Some(Commands::Downgrade {
+            name,
+            tag,
+            skip_verify,
+        }) => {
+            let options = InstallOptions {
+                skip_verify: *skip_verify,
+                ..Default::default()
+            };
+            downgrade(name, tag.as_deref(), &options).await?;
+        }
+        Some(Commands::Rollback { name }) => {
+            rollback(name).await?;
+        }
+        Some(Commands::Pin { name, requirement }) => {
+            pin(name, requirement.as_deref()).await?;
+        }
+        Some(Commands::Unpin { name }) => {
+            unpin(name).await?;
+        }
+        Some(Commands::Asset { name, pattern }) => {
+            asset(name, pattern.as_deref()).await?;
+        }
+        Some(Commands::Config { action }) => match action {
+            ConfigAction::Get { key } => config_get(key).await?,
+            ConfigAction::Set { key, value } => config_set(key, value).await?,
+            ConfigAction::List => config_list().await?,
+        },
         None => {
             list().await?;
         }
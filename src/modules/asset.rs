@@ -0,0 +1,28 @@
+use crate::utils::{errors::error, manifest::Manifest};
+use anyhow::{Context, Result};
+use color_print::cprintln;
+
+/// Prints the pattern picking the asset of `name` on update, or replaces it
+pub async fn asset(name: &str, pattern: Option<&str>) -> Result<()> {
+    let mut manifest = Manifest::load()?;
+    let app = manifest
+        .apps
+        .get_mut(name)
+        .context(error!(format!("{} is not installed", name)))?;
+
+    let Some(pattern) = pattern else {
+        match &app.asset_pattern {
+            Some(pattern) => cprintln!("<c,s>{}</> <y>{}", name, pattern),
+            None => cprintln!("<c,s>{}</> <y>has no asset pattern", name),
+        }
+        return Ok(());
+    };
+    app.asset_pattern = Some(pattern.to_string());
+    manifest.save()?;
+    cprintln!(
+        "<c,s>{}</> <g>picks assets matching</> <y>{}",
+        name,
+        pattern
+    );
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use crate::utils::config::{Config, KEYS};
+use anyhow::Result;
+use color_print::cprintln;
+
+pub async fn config_get(key: &str) -> Result<()> {
+    let config = Config::load()?;
+    println!("{}", config.get(key)?);
+    Ok(())
+}
+
+pub async fn config_set(key: &str, value: &str) -> Result<()> {
+    let mut config = Config::load()?;
+    config.set(key, value)?;
+    config.save()?;
+    cprintln!("<c,s>{}</> <g>set to</> <y>{}", key, config.get(key)?);
+    Ok(())
+}
+
+pub async fn config_list() -> Result<()> {
+    let config = Config::load()?;
+    for key in KEYS {
+        cprintln!("<c,s>{}</> <y>{}", key, config.get(key)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::{
+    api::{github::GITHUB, options::InstallOptions},
+    utils::{errors::error, manifest::Manifest},
+};
+use anyhow::{Context, Result};
+use color_print::{cformat, cprintln};
+use dialoguer::{theme::ColorfulTheme, Select};
+
+/// Installs a release of `name` other than the latest one, picked by `tag`
+/// or interactively from the releases of its repository
+pub async fn downgrade(name: &str, tag: Option<&str>, options: &InstallOptions) -> Result<()> {
+    GITHUB::check_rate_limit().await?;
+    let manifest = Manifest::load()?;
+    let app = manifest
+        .get(name)
+        .context(error!(format!("{} is not installed", name)))?;
+
+    let Some((owner, repo)) = app.github_repo() else {
+        return Err(error!(format!(
+            "{} does not come from github releases, use `appi rollback {}` to restore a version kept on disk",
+            name, name
+        )));
+    };
+
+    let releases: Vec<_> = GITHUB::get_releases(owner, repo)
+        .await?
+        .into_iter()
+        .filter(|release| {
+            release.tag_name.is_some()
+                && release.matches_filter(app.tag_filter.as_ref())
+                && release.has_appimage(app.update_info.as_ref())
+        })
+        .collect();
+    let tags: Vec<&str> = releases
+        .iter()
+        .filter_map(|release| release.tag_name.as_deref())
+        .collect();
+    if tags.is_empty() {
+        return Err(error!(format!(
+            "No release of {}/{} has an AppImage",
+            owner, repo
+        )));
+    }
+
+    let tag = match tag {
+        Some(tag) => *tags
+            .iter()
+            .find(|&&candidate| candidate == tag)
+            .context(error!(format!("{}/{} has no release {}", owner, repo, tag)))?,
+        None => {
+            let items: Vec<String> = releases
+                .iter()
+                .map(|release| {
+                    let tag = release.tag_name.as_deref().unwrap_or_default();
+                    let published = release
+                        .published_at
+                        .as_deref()
+                        .and_then(|date| date.split('T').next())
+                        .unwrap_or_default();
+                    let mut item = cformat!("{} <k>{}</>", tag, published);
+                    if release.prerelease.unwrap_or(false) {
+                        item.push_str(&cformat!(" <m>pre-release</>"));
+                    }
+                    if tag == app.tag {
+                        item.push_str(&cformat!(" <g>installed</>"));
+                    }
+                    item
+                })
+                .collect();
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(cformat!("<y>select a release of {}?", name))
+                .default(0)
+                .max_length(10)
+                .items(&items)
+                .interact()
+                .context(error!("No release selected"))?;
+            tags[selection]
+        }
+    };
+
+    if tag == app.tag {
+        cprintln!("<c>{} <y>is already at {}", name, tag);
+        return Ok(());
+    }
+
+    // Picking the latest release brings the app back to regular updates
+    let latest = GITHUB::get_appimage_release(
+        owner,
+        repo,
+        &app.channel,
+        app.tag_filter.as_ref(),
+        app.update_info.as_ref(),
+    )
+    .await?
+    .tag_name;
+    let options = InstallOptions {
+        seed: Some(app.file_path()?),
+        tag: Some(tag.to_string()).filter(|tag| Some(tag) != latest.as_ref()),
+        ..options.clone()
+    };
+    GITHUB::install(&app.name, owner, repo, app.update_info.as_ref(), &options).await?;
+
+    if let Some(current) = Manifest::load()?.get(&app.name) {
+        app.remove_stale_files(current)?;
+    }
+    Ok(())
+}
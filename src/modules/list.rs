@@ -1,39 +1,22 @@
-use crate::utils::tools::Tools;
+use crate::utils::manifest::Manifest;
 use anyhow::Result;
-use color_print::cprintln;
-use std::{fs, io};
+use color_print::cformat;
 
 pub async fn list() -> Result<()> {
-    let base_path = format!("/home/{}/Applications", Tools.get_user()?);
-    let mut repo_entries = fs::read_dir(&base_path)?
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()?;
-    repo_entries.sort();
+    let manifest = Manifest::load()?;
 
-    for repo_entry in repo_entries {
-        let repo_path = repo_entry;
-        if !repo_path.is_dir() {
-            continue;
+    for app in manifest.apps.values() {
+        let mut line = cformat!("<c,s>{}</> <y>{}</>", app.name, app.version);
+        if !app.channel.is_stable() {
+            line.push_str(&cformat!(" <m>[{}]</>", app.channel));
         }
-        let dir_entries = fs::read_dir(&repo_path)?;
-        for entry in dir_entries {
-            let path = entry?.path();
-            if !path.is_file() {
-                continue;
-            }
-            let file_name = path
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
-                .to_lowercase()
-                .replace(".appimage", "");
-            let parts: Vec<&str> = file_name.split('-').collect();
-            let name = parts[0];
-            let _creator = parts[1];
-            let version = parts[2].trim_start_matches('v');
-            cprintln!("<c,s>{}</> <y>{}", name, version);
+        if let Some(pin) = &app.pin {
+            line.push_str(&cformat!(" <b>({})</>", pin));
         }
+        if app.chosen {
+            line.push_str(&cformat!(" <b>(held at a chosen release)</>"));
+        }
+        println!("{}", line);
     }
     println!();
     Ok(())
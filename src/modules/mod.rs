@@ -1,3 +1,9 @@
+pub mod asset;
+pub mod config;
 pub mod delete;
+pub mod downgrade;
 pub mod list;
+pub mod outdated;
+pub mod pin;
+pub mod rollback;
 pub mod update;
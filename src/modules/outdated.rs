@@ -0,0 +1,84 @@
+use crate::{
+    modules::update::{check_all, Status},
+    utils::config::Config,
+};
+use anyhow::Result;
+use color_print::{cformat, cprintln};
+
+/// Exit status of `appi outdated` when updates are available
+pub const UPDATES_AVAILABLE: i32 = 100;
+
+/// Prints the installed and available version of the apps `update` would
+/// look at without updating anything, returning how many apps are outdated
+pub async fn outdated(names: &[String], exclude: &[String]) -> Result<usize> {
+    let checked = check_all(&Config::load()?, names, exclude).await?;
+
+    let header = ["NAME", "INSTALLED", "AVAILABLE", "PUBLISHED", "STATUS"].map(String::from);
+    let rows: Vec<[String; 5]> = checked
+        .iter()
+        .map(|(app, check)| {
+            let mut status = match &check.status {
+                Status::UpToDate => "up to date".to_string(),
+                Status::Outdated => "outdated".to_string(),
+                Status::Skipped(reason) => reason.to_string(),
+                Status::Failed(err) => format!("failed: {}", err),
+            };
+            if let Some(pin) = &app.pin {
+                status = format!("{} ({})", status, pin);
+            }
+            [
+                app.name.clone(),
+                app.version.clone(),
+                check.latest.clone().unwrap_or_else(|| "-".to_string()),
+                check.published.clone().unwrap_or_else(|| "-".to_string()),
+                status,
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+    let pad = |row: &[String; 5]| -> Vec<String> {
+        row.iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:<width$}", column, width = width))
+            .collect()
+    };
+
+    let columns = pad(&header);
+    cprintln!(
+        "<s>{}  {}  {}  {}  {}</>",
+        columns[0],
+        columns[1],
+        columns[2],
+        columns[3],
+        columns[4].trim_end()
+    );
+    for ((_, check), row) in checked.iter().zip(&rows) {
+        let columns = pad(row);
+        let status = columns[4].trim_end();
+        let status = match check.status {
+            Status::UpToDate => cformat!("<g>{}</>", status),
+            Status::Outdated => cformat!("<r>{}</>", status),
+            Status::Skipped(_) => cformat!("<k>{}</>", status),
+            Status::Failed(_) => cformat!("<r>{}</>", status),
+        };
+        cprintln!(
+            "<c,s>{}</>  <y>{}</>  <y>{}</>  {}  {}",
+            columns[0],
+            columns[1],
+            columns[2],
+            columns[3],
+            status
+        );
+    }
+
+    Ok(checked
+        .iter()
+        .filter(|(_, check)| check.status == Status::Outdated)
+        .count())
+}
@@ -0,0 +1,47 @@
+use crate::utils::{
+    errors::error,
+    manifest::{Manifest, Pin},
+};
+use anyhow::{Context, Result};
+use color_print::cprintln;
+use semver::VersionReq;
+
+pub async fn pin(name: &str, requirement: Option<&str>) -> Result<()> {
+    let pin = match requirement {
+        Some(requirement) => {
+            VersionReq::parse(requirement).context(error!(format!(
+                "{} is not a valid version requirement",
+                requirement
+            )))?;
+            Pin::Requirement(requirement.to_string())
+        }
+        None => Pin::Hold,
+    };
+
+    let mut manifest = Manifest::load()?;
+    let app = manifest
+        .apps
+        .get_mut(name)
+        .context(error!(format!("{} is not installed", name)))?;
+    cprintln!("<c,s>{}</> <g>{}", name, pin);
+    app.pin = Some(pin);
+    manifest.save()?;
+    Ok(())
+}
+
+pub async fn unpin(name: &str) -> Result<()> {
+    let mut manifest = Manifest::load()?;
+    let app = manifest
+        .apps
+        .get_mut(name)
+        .context(error!(format!("{} is not installed", name)))?;
+    // A release picked at install or by rollback holds the app just the same
+    let chosen = std::mem::take(&mut app.chosen);
+    if app.pin.take().is_none() && !chosen {
+        cprintln!("<c,s>{}</> <y>is not pinned", name);
+        return Ok(());
+    }
+    manifest.save()?;
+    cprintln!("<c,s>{}</> <g>unpinned", name);
+    Ok(())
+}
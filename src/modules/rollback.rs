@@ -0,0 +1,67 @@
+use crate::utils::{
+    appimage::AppImage, config::Config, errors::error, manifest::Manifest, staging::switch_current,
+};
+use anyhow::{Context, Result};
+use color_print::cprintln;
+use std::fs;
+
+pub async fn rollback(name: &str) -> Result<()> {
+    let mut manifest = Manifest::load()?;
+    let mut app = manifest
+        .remove(name)
+        .context(error!(format!("{} is not installed", name)))?;
+
+    let Some(mut previous) = app.previous.pop() else {
+        return Err(error!(format!(
+            "No previous version of {} to roll back to",
+            name
+        )));
+    };
+    let folder = previous
+        .folder
+        .clone()
+        .filter(|folder| folder.is_dir())
+        .context(error!(format!(
+            "Version {} of {} is no longer on disk",
+            previous.version, name
+        )))?;
+
+    // The app folder holding the versions, wherever it was installed to
+    let app_folder = folder
+        .parent()
+        .context(error!(format!("{} has no app folder", folder.display())))?;
+    switch_current(app_folder, &folder)?;
+    if let Some(current) = app.folder.filter(|current| *current != folder) {
+        fs::remove_dir_all(current)?;
+    }
+
+    // Keep track of integration files written by either version
+    for path in app.files {
+        if !previous.files.contains(&path) {
+            previous.files.push(path);
+        }
+    }
+    // Every version writes its icon to the same file of `icon_dir`
+    if let Some(icon_dir) = Config::load()?.icon_dir()? {
+        match AppImage.restore_icon(name, &previous.file_path()?, &icon_dir) {
+            Ok(icon) if !previous.files.contains(&icon) => previous.files.push(icon),
+            Ok(_) => {}
+            Err(err) => cprintln!("<y>Could not restore the icon of {}: {:#}", name, err),
+        }
+    }
+    previous.previous = app.previous;
+    previous.pin = app.pin;
+    // Otherwise the next update brings back the version just rolled back from
+    previous.chosen = true;
+    let version = previous.version.clone();
+    manifest.insert(previous);
+    manifest.save()?;
+
+    cprintln!("<c,s>{}</> <g>rolled back to</> <y>{}", name, version);
+    cprintln!(
+        "<y>{} is held at this version, run `appi unpin {}` to update it again",
+        name,
+        name
+    );
+    Ok(())
+}
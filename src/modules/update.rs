@@ -1,76 +1,504 @@
-#![allow(clippy::needless_late_init)]
-
 use crate::{
-    api::{aur::AUR, github::GITHUB},
-    utils::tools::Tools,
+    api::{
+        aur::AUR,
+        github::GITHUB,
+        local::LOCAL,
+        options::{InstallOptions, UpdateOptions},
+    },
+    utils::{
+        appimage::{matches_pattern, UpdateInfo},
+        config::Config,
+        errors::error,
+        manifest::{Installed, Manifest, Pin, Provider},
+        progress,
+        version::Version,
+        zsync::Zsync,
+    },
 };
-use anyhow::{Ok, Result};
+use anyhow::{Context, Ok, Result};
+use chrono::DateTime;
 use color_print::{cformat, cprintln};
-use indicatif::ProgressBar;
-use semver::Version;
-use std::{fs, time::Duration};
-
-pub async fn update() -> Result<()> {
-    let base_path = format!("/home/{}/Applications", Tools.get_user()?);
-    let repo_entries = fs::read_dir(&base_path)?;
-    for repo_entry in repo_entries {
-        let repo_path = repo_entry?.path();
-        if !repo_path.is_dir() {
-            continue;
-        }
-        let dir_entries = fs::read_dir(&repo_path)?;
-        for entry in dir_entries {
-            let path = entry?.path();
-            if !path.is_file() {
-                continue;
+use dialoguer::{theme::ColorfulTheme, Confirm, MultiSelect};
+use indicatif::{HumanBytes, ProgressBar};
+use semver::VersionReq;
+use std::{io::IsTerminal, sync::Arc, time::Duration};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// Whether an app has a newer version
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    UpToDate,
+    Outdated,
+    /// Not checked or nothing to compare against, with the reason
+    Skipped(&'static str),
+    /// Checking failed, with the error
+    Failed(String),
+}
+
+/// What checking an app for updates found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub status: Status,
+    /// Newest version found, the release to move to for pinned apps
+    pub latest: Option<String>,
+    /// The day the newest version was published
+    pub published: Option<String>,
+}
+
+impl Check {
+    fn new(outdated: bool, latest: Option<String>, published: Option<String>) -> Self {
+        let status = match outdated {
+            true => Status::Outdated,
+            false => Status::UpToDate,
+        };
+        Check {
+            status,
+            latest,
+            published,
+        }
+    }
+
+    fn skipped(reason: &'static str) -> Self {
+        Check {
+            status: Status::Skipped(reason),
+            latest: None,
+            published: None,
+        }
+    }
+
+    fn failed(err: anyhow::Error) -> Self {
+        Check {
+            status: Status::Failed(format!("{:#}", err)),
+            latest: None,
+            published: None,
+        }
+    }
+
+    /// The status of `app` as printed by `update`
+    pub fn describe(&self, app: &Installed) -> String {
+        let mut line = cformat!("<c,s>{}</> <y>{}</> ", app.name, app.version);
+        line.push_str(&match &self.status {
+            Status::UpToDate => cformat!("<g>is up to date</>"),
+            Status::Outdated => cformat!("<r>is outdated</>"),
+            Status::Skipped(reason) => cformat!("<y>{}</>", reason),
+            Status::Failed(err) => cformat!("<r>could not be checked:</> {}", err),
+        });
+        if !app.channel.is_stable() {
+            line.push_str(&cformat!(" <m>[{}]</>", app.channel));
+        }
+        if let Some(pin @ Pin::Requirement(_)) = &app.pin {
+            line.push_str(&cformat!(" <b>({})</>", pin));
+        }
+        line
+    }
+}
+
+/// Checks the upstream declared by the AppImage itself, `None` when it
+/// declares none
+async fn check_update_info(app: &Installed) -> Result<Option<Check>> {
+    let check = match &app.update_info {
+        Some(update_info @ UpdateInfo::GhReleases { .. }) => {
+            let filter = app.tag_filter.as_ref();
+            let (tag, published) = GITHUB::get_release_tag(update_info, filter).await?;
+            let outdated =
+                Version::from_tag(&app.tag, filter).is_older_than(&Version::from_tag(&tag, filter));
+            Check::new(outdated, Some(tag), published)
+        }
+        Some(UpdateInfo::Zsync { url }) => {
+            let zsync = Zsync::fetch(url).await?;
+            let outdated = match zsync.sha1.clone() {
+                Some(sha1) => {
+                    let file_path = app.file_path()?;
+                    tokio::task::spawn_blocking(move || {
+                        sha1.verify(&file_path.to_string_lossy()).is_err()
+                    })
+                    .await?
+                }
+                None => zsync.filename != app.asset,
+            };
+            Check::new(outdated, Some(zsync.filename), None)
+        }
+        None => return Ok(None),
+    };
+    Ok(Some(check))
+}
+
+/// Checks the newest release in the channel an app follows
+async fn check_channel(app: &Installed) -> Result<Check> {
+    let Some((owner, repo)) = app.github_repo() else {
+        return Ok(Check::skipped(
+            "follows a channel but is not released on github",
+        ));
+    };
+    let filter = app.tag_filter.as_ref();
+    let release =
+        GITHUB::get_appimage_release(owner, repo, &app.channel, filter, app.update_info.as_ref())
+            .await?;
+    let published = release.published();
+    let released_at = release
+        .published_at
+        .as_deref()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok());
+    let tag = release.tag_name.context(error!("No version found"))?;
+    // Rolling tags such as `nightly` are published again under the same name
+    let outdated = match tag == app.tag {
+        true => released_at.is_some_and(|date| date > app.installed_at),
+        false => {
+            Version::from_tag(&app.tag, filter).is_older_than(&Version::from_tag(&tag, filter))
+        }
+    };
+    Ok(Check::new(outdated, Some(tag), published))
+}
+
+/// Finds the newest version matching the requirement an app is pinned to
+async fn check_pinned(app: &Installed, requirement: &str) -> Result<Check> {
+    let requirement = VersionReq::parse(requirement)?;
+    let filter = app.tag_filter.as_ref();
+    let current = Version::from_tag(&app.tag, filter).to_semver();
+    let accepts = |version: &semver::Version| {
+        requirement.matches(version) && current.as_ref().is_none_or(|current| version > current)
+    };
+
+    let Some((owner, repo)) = app.github_repo() else {
+        if app.provider != Provider::Aur {
+            return Ok(Check::skipped("has no versions to match its pin against"));
+        }
+        let (latest, published) = AUR::get_latest_version(&app.repo).await?;
+        let outdated = latest.to_semver().is_some_and(|latest| accepts(&latest));
+        return Ok(Check::new(outdated, Some(latest.tag), published));
+    };
+
+    // Releases are listed newest first, the installed one ends the scan as
+    // nothing older can be an update
+    let mut newest: Option<(semver::Version, String, Option<String>)> = None;
+    GITHUB::scan_releases(owner, repo, |release| {
+        let Some(tag) = release.tag_name.as_deref() else {
+            return true;
+        };
+        if tag == app.tag {
+            return false;
+        }
+        if !release.in_channel(&app.channel)
+            || !release.matches_filter(filter)
+            || !release.has_appimage(app.update_info.as_ref())
+        {
+            return true;
+        }
+        let version = Version::from_tag(tag, filter).to_semver();
+        if let Some(version) = version.filter(|version| accepts(version)) {
+            if newest.as_ref().is_none_or(|(best, ..)| version > *best) {
+                newest = Some((version, tag.to_string(), release.published()));
             }
-            let file_name = path
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
-                .to_lowercase()
-                .replace(".appimage", "");
-
-            let parts: Vec<&str> = file_name.split('-').collect();
-            let name = parts[0];
-            let creator = parts[1];
-            let version = parts[2].trim_start_matches('v');
-            let appimage = cformat!("<c,s>{}</> <y>{}", name, version);
-
-            let pb = ProgressBar::new_spinner();
+        }
+        true
+    })
+    .await?;
+    Ok(match newest {
+        Some((_, tag, published)) => Check::new(true, Some(tag), published),
+        None => Check::new(false, None, None),
+    })
+}
+
+async fn check(app: &Installed) -> Result<Check> {
+    if app.chosen {
+        return Ok(Check::skipped("was installed at a chosen release"));
+    }
+    match &app.pin {
+        Some(Pin::Hold) => return Ok(Check::skipped("is pinned")),
+        Some(Pin::Requirement(requirement)) => return check_pinned(app, requirement).await,
+        None => {}
+    }
+    if !app.channel.is_stable() {
+        return check_channel(app).await;
+    }
+
+    if let Some(check) = check_update_info(app).await? {
+        return Ok(check);
+    } else if app.provider == Provider::Local {
+        return Ok(Check::skipped("declares no update information"));
+    }
+    let (latest_version, published) = if app.provider == Provider::Aur {
+        AUR::get_latest_version(&app.repo).await?
+    } else {
+        let owner = app.owner.as_deref().unwrap_or_default();
+        GITHUB::get_latest_version(&app.repo, owner, app.tag_filter.as_ref()).await?
+    };
+    let outdated =
+        Version::from_tag(&app.tag, app.tag_filter.as_ref()).is_older_than(&latest_version);
+    Ok(Check::new(outdated, Some(latest_version.tag), published))
+}
+
+/// Checks the installed apps named in `names`, or all of them, except those
+/// in `exclude`, up to `check_jobs` at a time, in name order. An app that
+/// cannot be checked is reported as failed, the others still are
+pub async fn check_all(
+    config: &Config,
+    names: &[String],
+    exclude: &[String],
+) -> Result<Vec<(Installed, Check)>> {
+    let mut apps = Manifest::load()?.apps;
+    if let Some(name) = names.iter().find(|name| !apps.contains_key(*name)) {
+        return Err(error!(format!("{} is not installed", name)));
+    }
+    apps.retain(|name, _| (names.is_empty() || names.contains(name)) && !exclude.contains(name));
+
+    let checks = Arc::new(Semaphore::new(config.check_jobs()));
+    let mut tasks = JoinSet::new();
+    for app in apps.into_values() {
+        let checks = checks.clone();
+        tasks.spawn(async move {
+            let _permit = checks.acquire_owned().await;
+            let pb = progress::add(ProgressBar::new_spinner());
             pb.enable_steady_tick(Duration::from_millis(120));
-            pb.set_message(cformat!("<y>{} <c>- Checking for updates...", name));
-
-            let appimage_version = Version::parse(version)?;
-            let latest_version;
-            if creator.to_lowercase() == "aur" {
-                latest_version = AUR::get_latest_version(&name.replace('_', "-")).await?;
-            } else {
-                latest_version =
-                    GITHUB::get_latest_version(&name.replace('_', "-"), &creator.replace('_', "-"))
-                        .await?;
-            };
+            pb.set_message(cformat!("<y>{} <c>- Checking for updates...", app.name));
+            let check = check(&app).await.unwrap_or_else(Check::failed);
+            pb.finish_and_clear();
+            (app, check)
+        });
+    }
 
-            if appimage_version < latest_version {
-                pb.finish_and_clear();
-                cprintln!("{} <r>is outdated</>", appimage);
-                fs::remove_dir_all(format!("{base_path}/{name}"))?;
-
-                if creator.to_lowercase() == "aur" {
-                    let name = &name.replace('_', "-");
-                    AUR::download(name).await?;
-                } else {
-                    let name = &name.replace('_', "-");
-                    let creator = &creator.replace('_', "-");
-                    let url = &format!("{}/{}", &creator, &name);
-                    GITHUB::download(url).await?;
-                }
-            } else {
-                pb.finish_and_clear();
-                cprintln!("{} <g>is up to date</>", appimage);
+    let mut checked = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        checked.push(result?);
+    }
+    checked.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    Ok(checked)
+}
+
+/// Installs the newer version of `app`, `tag` being the release its pin
+/// settled on
+async fn install_update(
+    app: &Installed,
+    tag: Option<String>,
+    options: InstallOptions,
+) -> Result<()> {
+    // The new version is staged next to the installed one, which is only
+    // replaced once the update succeeded
+    let options = InstallOptions {
+        seed: Some(app.file_path()?),
+        tag: tag.clone(),
+        // Other updates are downloading, a question would garble their bars
+        noprompt: true,
+        ..options
+    };
+    match (&tag, app.github_repo(), &app.update_info) {
+        (Some(_), Some((owner, repo)), update_info) => {
+            GITHUB::install(&app.name, owner, repo, update_info.as_ref(), &options).await?
+        }
+        (_, _, Some(update_info @ UpdateInfo::GhReleases { owner, repo, .. })) => {
+            GITHUB::install(&app.name, owner, repo, Some(update_info), &options).await?
+        }
+        (_, _, Some(UpdateInfo::Zsync { url })) => {
+            LOCAL::download(&app.name, url, &options).await?
+        }
+        (_, _, None) if app.provider == Provider::Aur => AUR::install(&app.repo, &options).await?,
+        (_, _, None) => {
+            let owner = app.owner.as_deref().unwrap_or_default();
+            GITHUB::install(&app.name, owner, &app.repo, None, &options).await?
+        }
+    }
+
+    Manifest::update(|manifest| {
+        if let Some(current) = manifest.apps.get_mut(&app.name) {
+            // The pin picked this release, not the user
+            if tag.is_some() {
+                current.chosen = false;
+            }
+            // Integration files of the old version the new one did not replace
+            app.remove_stale_files(current)?;
+        }
+        Ok(())
+    })
+}
+
+/// Names of the apps in each outcome of an update run
+#[derive(Default)]
+struct Summary {
+    updated: Vec<String>,
+    up_to_date: Vec<String>,
+    skipped: Vec<String>,
+    /// With the error that stopped them
+    failed: Vec<(String, String)>,
+}
+
+impl Summary {
+    fn print(&mut self) {
+        self.updated.sort();
+        self.failed.sort();
+        cprintln!("\n<s>Summary</>");
+        let groups = [
+            (cformat!("<g>updated</>"), &self.updated),
+            (cformat!("<g>up to date</>"), &self.up_to_date),
+            (cformat!("<y>skipped</>"), &self.skipped),
+        ];
+        for (label, names) in groups {
+            if !names.is_empty() {
+                cprintln!("{} ({}): <c>{}</>", label, names.len(), names.join(", "));
+            }
+        }
+        if !self.failed.is_empty() {
+            cprintln!("<r>failed</> ({}):", self.failed.len());
+            for (name, err) in &self.failed {
+                cprintln!("  <c,s>{}</>: {}", name, err);
+            }
+        }
+    }
+}
+
+/// How much downloading the new version of `app` takes, `None` when the
+/// source does not tell
+async fn download_size(app: &Installed, check: &Check) -> Option<u64> {
+    if let Some(UpdateInfo::Zsync { url }) = &app.update_info {
+        return Zsync::fetch(url).await.ok().map(|zsync| zsync.length);
+    }
+    if app.provider == Provider::Aur {
+        let url = AUR::get_appimage_url(&app.repo).await.ok()?;
+        let response = reqwest::Client::new().head(url).send().await.ok()?;
+        return response.content_length();
+    }
+
+    let (owner, repo) = app.github_repo()?;
+    let tag = check.latest.as_deref()?;
+    let release = GITHUB::get(&GITHUB::release_url(owner, repo, tag))
+        .await
+        .ok()?;
+    let assets = release.assets?;
+    let asset = assets.iter().find(|asset| {
+        let name = asset.name.as_deref().unwrap_or_default();
+        match (&app.asset_pattern, &app.update_info) {
+            (Some(pattern), _) => matches_pattern(pattern, name),
+            (None, Some(update_info)) => update_info.matches(name),
+            (None, None) => name.to_lowercase().ends_with(".appimage"),
+        }
+    })?;
+    asset.size
+}
+
+/// Lists the updates about to be installed with their download size and
+/// asks to go ahead, the way pacman does
+async fn confirm(outdated: &[(Installed, Check)]) -> Result<bool> {
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(Duration::from_millis(120));
+    pb.set_message(cformat!("<c>Looking up download sizes..."));
+    let mut total = 0;
+    let mut unknown = false;
+    let mut lines = Vec::new();
+    for (app, check) in outdated {
+        let size = download_size(app, check).await;
+        total += size.unwrap_or_default();
+        unknown |= size.is_none();
+        lines.push(cformat!(
+            "  <c,s>{}</> <y>{}</> -> <g>{}</> <k>{}</>",
+            app.name,
+            app.version,
+            check.latest.as_deref().unwrap_or("?"),
+            size.map_or("?".to_string(), |size| HumanBytes(size).to_string())
+        ));
+    }
+    pb.finish_and_clear();
+
+    cprintln!("\n<s>Apps ({})</>", outdated.len());
+    for line in lines {
+        println!("{}", line);
+    }
+    cprintln!(
+        "\n<s>Total Download Size:</> {}{}\n",
+        HumanBytes(total),
+        if unknown { " (some sizes unknown)" } else { "" }
+    );
+    Ok(Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(cformat!("<y>Proceed with update?"))
+        .default(true)
+        .interact()?)
+}
+
+/// Checks the apps picked by `selection` at once, then installs the updates
+/// found, up to `download_jobs` at a time. A failing app does not stop the
+/// others, the run only fails once every app had its turn
+pub async fn update(options: &InstallOptions, selection: &UpdateOptions) -> Result<()> {
+    let config = Config::load()?;
+    let mut summary = Summary::default();
+    let mut outdated = Vec::new();
+    for (app, check) in check_all(&config, &selection.names, &selection.exclude).await? {
+        cprintln!("{}", check.describe(&app));
+        match &check.status {
+            Status::Outdated => outdated.push((app, check)),
+            Status::UpToDate => summary.up_to_date.push(app.name),
+            Status::Skipped(_) => summary.skipped.push(app.name),
+            Status::Failed(err) => summary.failed.push((app.name, err.clone())),
+        }
+    }
+
+    if selection.interactive && !outdated.is_empty() {
+        let items: Vec<String> = outdated
+            .iter()
+            .map(|(app, check)| {
+                cformat!(
+                    "{} <y>{}</> -> <g>{}</>",
+                    app.name,
+                    app.version,
+                    check.latest.as_deref().unwrap_or("?")
+                )
+            })
+            .collect();
+        let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(cformat!("<y>select the apps to update"))
+            .items(&items)
+            .defaults(&vec![true; items.len()])
+            .interact()?;
+        let mut index = 0;
+        outdated.retain(|(app, _)| {
+            let keep = picked.contains(&index);
+            if !keep {
+                summary.skipped.push(app.name.clone());
             }
+            index += 1;
+            keep
+        });
+    }
+
+    if !outdated.is_empty()
+        && !selection.noconfirm
+        && std::io::stdin().is_terminal()
+        && !confirm(&outdated).await?
+    {
+        summary
+            .skipped
+            .extend(outdated.drain(..).map(|(app, _)| app.name));
+    }
+
+    let downloads = Arc::new(Semaphore::new(config.download_jobs()));
+    let mut tasks = JoinSet::new();
+    for (app, check) in outdated {
+        // Pinned apps move to the release their requirement settled on
+        let tag = match app.pin {
+            Some(Pin::Requirement(_)) if app.github_repo().is_some() => check.latest,
+            _ => None,
+        };
+        let downloads = downloads.clone();
+        let options = options.clone();
+        tasks.spawn(async move {
+            let _permit = downloads.acquire_owned().await;
+            let result = install_update(&app, tag, options).await;
+            (app.name, result)
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        let (name, result) = result?;
+        if let Err(err) = result {
+            cprintln!("<c,s>{}</> <r>failed to update:</> {:#}", name, err);
+            summary.failed.push((name, format!("{:#}", err)));
+        } else {
+            summary.updated.push(name);
         }
     }
+
+    summary.print();
+    if !summary.failed.is_empty() {
+        return Err(error!(format!(
+            "{} app(s) failed to update",
+            summary.failed.len()
+        )));
+    }
     Ok(())
 }
@@ -1,22 +1,214 @@
-use crate::utils::{errors::error, tools::Tools};
+use crate::utils::{
+    arch::Arch,
+    checksum::Checksum,
+    config::Config,
+    elf::Elf,
+    errors::error,
+    interrupt,
+    paths::Paths,
+    progress,
+    signature::Signature,
+    squashfs::{EntryKind, SquashFs},
+    staging::Staging,
+    zsync::Zsync,
+};
 use anyhow::Result;
-use reqwest::Client;
+use color_print::cformat;
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
+    fmt,
     fs::{self, Permissions},
+    io,
     os::unix::prelude::PermissionsExt,
     path::{Path, PathBuf},
-    process::Command,
+    time::Duration,
 };
 use tokio::{
-    fs::{create_dir_all, set_permissions, File},
+    fs::{create_dir_all, set_permissions, OpenOptions},
     io::AsyncWriteExt,
 };
+
+const UPDATE_INFO_SECTION: &str = ".upd_info";
+
+/// Update source declared by the AppImage in its `.upd_info` section
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "transport", rename_all = "kebab-case")]
+pub enum UpdateInfo {
+    /// `gh-releases-zsync|owner|repo|release|pattern`, `release` is either
+    /// `latest` or a tag and `pattern` matches the asset name
+    GhReleases {
+        owner: String,
+        repo: String,
+        release: String,
+        pattern: String,
+        /// The pattern names the `.zsync` file published next to the AppImage
+        zsync: bool,
+    },
+    /// `zsync|url` pointing at a `.zsync` file
+    Zsync { url: String },
+}
+
+impl UpdateInfo {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        let parts: Vec<&str> = raw.split('|').collect();
+        match parts.as_slice() {
+            [transport @ ("gh-releases-zsync" | "gh-releases-direct"), owner, repo, release, pattern]
+                if !owner.is_empty() && !repo.is_empty() && !pattern.is_empty() =>
+            {
+                Some(UpdateInfo::GhReleases {
+                    owner: owner.to_string(),
+                    repo: repo.to_string(),
+                    release: if release.is_empty() {
+                        "latest".to_string()
+                    } else {
+                        release.to_string()
+                    },
+                    pattern: pattern.to_string(),
+                    zsync: *transport == "gh-releases-zsync",
+                })
+            }
+            ["zsync", url] if url.starts_with("http") => Some(UpdateInfo::Zsync {
+                url: url.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether a release asset is the AppImage this update information describes
+    pub fn matches(&self, asset_name: &str) -> bool {
+        match self {
+            UpdateInfo::GhReleases { pattern, zsync, .. } => {
+                let pattern = match zsync {
+                    true => pattern.strip_suffix(".zsync").unwrap_or(pattern),
+                    false => pattern,
+                };
+                glob(pattern.as_bytes(), asset_name.as_bytes())
+            }
+            UpdateInfo::Zsync { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for UpdateInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateInfo::GhReleases {
+                owner,
+                repo,
+                release,
+                pattern,
+                ..
+            } => write!(f, "github {}/{} ({}, {})", owner, repo, release, pattern),
+            UpdateInfo::Zsync { url } => write!(f, "zsync {}", url),
+        }
+    }
+}
+
+/// Matches `*` and `?` wildcards the way the AppImage update tools do
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], text) || (!text.is_empty() && glob(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => glob(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+/// Whether `asset_name` matches the asset pattern remembered for an app
+pub fn matches_pattern(pattern: &str, asset_name: &str) -> bool {
+    glob(pattern.as_bytes(), asset_name.as_bytes())
+}
+
+/// A pattern picking the same asset in later releases, the version in its
+/// name becomes a wildcard
+pub fn asset_pattern(asset_name: &str, tag: &str) -> String {
+    let version = tag.trim_start_matches(|c: char| !c.is_ascii_digit());
+    // Only the version as a whole, the `6` of `v6` is not the one in `x86_64`
+    let digit_at = |at: usize| {
+        let rest = asset_name[at..]
+            .strip_prefix('.')
+            .unwrap_or(&asset_name[at..]);
+        rest.starts_with(|c: char| c.is_ascii_digit())
+    };
+    let whole = |&(start, _): &(usize, &str)| {
+        let before = asset_name[..start].chars().next_back();
+        !before.is_some_and(|c| c.is_ascii_digit() || c == '.') && !digit_at(start + version.len())
+    };
+    if !version.is_empty() {
+        if let Some((start, _)) = asset_name.match_indices(version).find(whole) {
+            let end = start + version.len();
+            return format!("{}*{}", &asset_name[..start], &asset_name[end..]);
+        }
+    }
+
+    // Otherwise any dotted run of numbers, `x86_64` has no dot and stays
+    let mut pattern = String::new();
+    let mut rest = asset_name;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        pattern.push_str(&rest[..start]);
+        let run = &rest[start..];
+        let end = run
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(run.len());
+        let number = run[..end].trim_end_matches('.');
+        match number.contains('.') {
+            true => pattern.push('*'),
+            false => pattern.push_str(number),
+        }
+        rest = &run[number.len()..];
+    }
+    pattern.push_str(rest);
+    pattern
+}
+
 pub struct AppImage;
 
 impl AppImage {
-    pub async fn download(&self, url: &str, file_path: &str) -> Result<()> {
+    /// Downloads `url` to `file_path`, sending `headers` with every request
+    pub async fn download(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        file_path: &str,
+        name: &str,
+    ) -> Result<()> {
+        let part_path = AppImage.part_path(url)?;
+        if let Some(parent) = part_path.parent() {
+            create_dir_all(parent).await?;
+        }
+        // The ETag or Last-Modified the partial file was downloaded with, the
+        // server only continues it while it still serves the same file
+        let validator_path = part_path.with_extension("validator");
+        let validator = tokio::fs::read_to_string(&validator_path).await.ok();
+        let resume_from = match (tokio::fs::metadata(&part_path).await, &validator) {
+            (Ok(metadata), Some(_)) => metadata.len(),
+            _ => 0,
+        };
+
         let client = Client::new();
-        let response = client.get(url).send().await?;
+        let mut request = client.get(url).headers(headers.clone());
+        if let (true, Some(validator)) = (resume_from > 0, &validator) {
+            request = request
+                .header(RANGE, format!("bytes={}-", resume_from))
+                .header(IF_RANGE, validator.trim());
+        }
+        let mut response = request.send().await?;
+
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file is stale or already complete, start over
+            tokio::fs::remove_file(&part_path).await?;
+            response = client.get(url).headers(headers).send().await?;
+        }
 
         if !response.status().is_success() {
             return Err(error!(
@@ -24,83 +216,360 @@ impl AppImage {
             ));
         }
 
+        // Any other answer is the whole file, which replaces the partial one
+        let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+        if !resumed {
+            let received = response.headers();
+            let validator = received
+                .get(ETAG)
+                .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+                .or(received.get(LAST_MODIFIED))
+                .and_then(|validator| validator.to_str().ok());
+            match validator {
+                Some(validator) => tokio::fs::write(&validator_path, validator).await?,
+                None => {
+                    let _ = tokio::fs::remove_file(&validator_path).await;
+                }
+            }
+        }
+        let mut output = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&part_path)
+            .await?;
+        let mut written = if resumed { resume_from } else { 0 };
+        let expected = response.content_length().map(|length| length + written);
+
+        let pb = match expected {
+            Some(total) => {
+                let pb = progress::add(ProgressBar::new(total));
+                pb.set_style(
+                    ProgressStyle::with_template(
+                        "{msg} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+                    )?
+                    .progress_chars("=> "),
+                );
+                pb
+            }
+            None => {
+                let pb = progress::add(ProgressBar::new_spinner());
+                pb.set_style(ProgressStyle::with_template(
+                    "{spinner} {msg} {bytes} {bytes_per_sec}",
+                )?);
+                pb.enable_steady_tick(Duration::from_millis(120));
+                pb
+            }
+        };
+        pb.set_message(cformat!("<c>Downloading {}...", name));
+        pb.set_position(written);
+
+        let mut guard = interrupt::guard();
+        let result = loop {
+            tokio::select! {
+                chunk = response.chunk() => match chunk {
+                    Ok(Some(chunk)) => {
+                        if let Err(err) = output.write_all(&chunk).await {
+                            break Err(err.into());
+                        }
+                        written += chunk.len() as u64;
+                        pb.set_position(written);
+                    }
+                    Ok(None) => break Ok(()),
+                    Err(err) => break Err(err.into()),
+                },
+                _ = guard.interrupted() => {
+                    break Err(error!("Download interrupted"));
+                }
+            }
+        };
+        output.flush().await?;
+        drop(output);
+        pb.finish_and_clear();
+
+        if let Err(err) = result {
+            // Keep what was downloaded so the next attempt can resume
+            progress::println(cformat!(
+                "<y>Partial download of <c>{}</c> kept for resume ({} bytes)",
+                name,
+                written
+            ));
+            return Err(err);
+        }
+
+        if let Some(expected) = expected {
+            if written != expected {
+                tokio::fs::remove_file(&part_path).await?;
+                let _ = tokio::fs::remove_file(&validator_path).await;
+                return Err(error!(format!(
+                    "Download incomplete, expected {} bytes but got {}",
+                    expected, written
+                )));
+            }
+        }
+
         let dir_path = Path::new(&file_path).parent().unwrap();
         create_dir_all(dir_path).await?;
-        let mut output = File::create(&file_path).await?;
-        let bytes = response.bytes().await?;
-        output.write_all(&bytes).await?;
+        if tokio::fs::rename(&part_path, file_path).await.is_err() {
+            // The cache may live on another filesystem
+            tokio::fs::copy(&part_path, file_path).await?;
+            tokio::fs::remove_file(&part_path).await?;
+        }
+        let _ = tokio::fs::remove_file(&validator_path).await;
 
         let permissions = Permissions::from_mode(0o755);
         set_permissions(file_path, permissions).await?;
         Ok(())
     }
-    pub fn extract(&self, file_path: &str) -> Result<()> {
-        let dir_path = Path::new(&file_path).parent().unwrap();
-        std::env::set_current_dir(dir_path)?;
-        Command::new(file_path).arg("--appimage-extract").output()?;
-
-        Ok(())
+    /// Rebuilds the new version from `seed` with zsync when possible and
+    /// downloads the whole file otherwise
+    pub async fn download_delta(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        file_path: &str,
+        name: &str,
+        zsync: Option<&Zsync>,
+        seed: Option<&Path>,
+    ) -> Result<()> {
+        if let (Some(zsync), Some(seed)) = (zsync, seed.filter(|seed| seed.is_file())) {
+            match zsync.sync(seed, file_path, name).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => progress::println(cformat!(
+                    "<c,s>{}</> <y>delta update not possible, downloading the whole file</>",
+                    name
+                )),
+                Err(err) => progress::println(cformat!(
+                    "<c,s>{}</> <y>delta update failed, downloading the whole file</> <k>({})</>",
+                    name,
+                    err
+                )),
+            }
+        }
+        self.download(url, headers, file_path, name).await
     }
-    pub fn integrate(&self, file_path: &str, name: &str) -> Result<()> {
-        let desktop_applications_path =
-            format!("/home/{}/.local/share/applications", Tools.get_user()?);
-        let desktop_applications_path = std::path::Path::new(&desktop_applications_path);
-
-        let appimage_path = std::path::PathBuf::from(file_path);
-        let appimage_dir = appimage_path.parent().unwrap();
-        let appimage_extracted_dir = appimage_dir.join("squashfs-root");
-        let exec_path = appimage_extracted_dir.join("AppRun");
-
-        let entries = appimage_extracted_dir.clone();
-
-        let desktop_file = entries
-            .read_dir()?
-            .filter_map(|entry| {
-                let path = entry.ok()?.path();
-                if path.is_file() && path.extension().unwrap_or_default() == "desktop" {
-                    Some(path.to_string_lossy().to_string())
-                } else {
-                    None
+    /// Partial downloads are keyed by url so a stale file is never resumed
+    /// with the contents of another release
+    fn part_path(&self, url: &str) -> Result<PathBuf> {
+        let key = hex::encode(Sha256::digest(url.as_bytes()));
+        Ok(Paths
+            .cache_home()?
+            .join("appi")
+            .join("downloads")
+            .join(format!("{}.part", &key[..16])))
+    }
+    /// Removes the download of `name` when it does not match `checksum`,
+    /// unless `skip` is set
+    pub fn verify(
+        &self,
+        name: &str,
+        file_path: &str,
+        checksum: Option<&Checksum>,
+        skip: bool,
+    ) -> Result<()> {
+        match checksum {
+            Some(_) if skip => {
+                progress::println(cformat!(
+                    "<c,s>{}</> <y>skipping checksum verification</>",
+                    name
+                ));
+            }
+            Some(checksum) => {
+                if let Err(err) = checksum.verify(file_path) {
+                    fs::remove_file(file_path)?;
+                    if let Some(dir_path) = Path::new(file_path).parent() {
+                        let _ = fs::remove_dir(dir_path);
+                    }
+                    return Err(err);
                 }
-            })
-            .next()
-            .ok_or_else(|| error!("No desktop file found"))?;
+                progress::println(cformat!(
+                    "<c,s>{}</> <g>checksum verified</> <k>({})</>",
+                    name,
+                    checksum.algorithm
+                ));
+            }
+            None => {
+                progress::println(cformat!(
+                    "<c,s>{}</> <y>no checksum published, skipping verification</>",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+    /// Refuses AppImages whose runtime is built for another architecture
+    pub fn check_arch(&self, file_path: &str) -> Result<()> {
+        let machine = Elf::read(Path::new(file_path))?.machine;
+        if let (Some(arch), Some(host)) = (Arch::from_machine(machine), Arch::host()) {
+            if arch != host {
+                return Err(error!(format!(
+                    "This AppImage is built for {}, this machine is {}",
+                    arch, host
+                )));
+            }
+        }
+        Ok(())
+    }
+    pub fn section(&self, file_path: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        Elf::read(Path::new(file_path))?.read_section(name)
+    }
+    pub fn update_info(&self, file_path: &str) -> Result<Option<UpdateInfo>> {
+        let section = self.section(file_path, UPDATE_INFO_SECTION)?;
+        Ok(section.and_then(|data| UpdateInfo::parse(&String::from_utf8_lossy(&data))))
+    }
+    /// `X-AppImage-Version` from the embedded desktop entry
+    pub fn version(&self, file_path: &str) -> Result<Option<String>> {
+        let squashfs = SquashFs::open(Path::new(file_path))?;
+        let Some(desktop_file) = squashfs
+            .read_dir("/")?
+            .into_iter()
+            .find(|entry| entry.name.ends_with(".desktop"))
+        else {
+            return Ok(None);
+        };
+        let content = squashfs.read_file(&desktop_file.name)?;
+        Ok(String::from_utf8_lossy(&content)
+            .lines()
+            .find_map(|line| line.strip_prefix("X-AppImage-Version="))
+            .map(|version| version.trim().to_string())
+            .filter(|version| !version.is_empty()))
+    }
+    /// Reports the signature embedded in the download of `name` and removes
+    /// it when the configured policy rejects it
+    pub fn check_signature(
+        &self,
+        name: &str,
+        file_path: &str,
+        config: &Config,
+    ) -> Result<Signature> {
+        let signature = Signature::check(Path::new(file_path))?;
+        progress::println(match &signature {
+            Signature::Signed { .. } => cformat!("<c,s>{}</> <g>signature: {}</>", name, signature),
+            Signature::Invalid(_) => cformat!("<c,s>{}</> <r>signature: {}</>", name, signature),
+            _ => cformat!("<c,s>{}</> <y>signature: {}</>", name, signature),
+        });
 
-        let icon_extensions = ["svg", "png", "jpg", "jpeg", "bmp", "ico", "webp"];
-        let mut icon = "None".to_string();
-        for entry in entries.read_dir()? {
-            let path = entry.as_ref().unwrap().path();
-            if path.is_file() {
-                let extension = path
-                    .extension()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_lowercase();
-                if icon_extensions.iter().any(|&ext| ext == extension) {
-                    icon = path.to_string_lossy().to_string();
-                    break;
-                }
+        let rejected = match signature {
+            Signature::Signed { .. } => false,
+            Signature::Invalid(_) => true,
+            _ => config.require_signature(),
+        };
+        if rejected {
+            fs::remove_file(file_path)?;
+            if let Some(dir_path) = Path::new(file_path).parent() {
+                let _ = fs::remove_dir(dir_path);
             }
+            return Err(error!(format!(
+                "Refusing to install an AppImage that is {}. Trusted keys are read from {}",
+                signature,
+                Signature::trusted_keys_dir()?.display()
+            )));
         }
+        Ok(signature)
+    }
+    pub fn digest(&self, file_path: &str) -> Result<String> {
+        let mut file = fs::File::open(file_path)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    }
+    /// Checks, extracts and integrates the AppImage downloaded into `staging`,
+    /// returning the files it owns, its digest and its update information
+    pub fn install(
+        &self,
+        staging: &mut Staging,
+        checksum: Option<&Checksum>,
+        skip_verify: bool,
+        config: &Config,
+    ) -> Result<(Vec<PathBuf>, String, Option<UpdateInfo>)> {
+        let file_path = staging.file_path();
+        self.verify(&staging.name, &file_path, checksum, skip_verify)?;
+        self.check_arch(&file_path)?;
+        self.check_signature(&staging.name, &file_path, config)?;
 
-        if icon == "None" {
-            return Err(error!("No icon found"));
+        let pb = progress::add(ProgressBar::new_spinner());
+        pb.enable_steady_tick(Duration::from_millis(120));
+        pb.set_message(cformat!("<c>Installing {}...", staging.name));
+        let digest = self.digest(&file_path)?;
+        let update_info = self.update_info(&file_path)?;
+        if config.extract() {
+            self.extract(&file_path)?;
         }
+        let mut files = vec![staging.app_folder.clone()];
+        let integrated = self.integrate(staging, config);
+        pb.finish_and_clear();
+        files.extend(integrated?);
+        Ok((files, digest, update_info))
+    }
+    /// Installs what `staging` holds once `downloaded` succeeded with the
+    /// checksum to verify, then commits or rolls it back. Hashing and
+    /// unpacking run on the blocking pool so other downloads keep going
+    pub async fn install_staged(
+        &self,
+        mut staging: Staging,
+        downloaded: Result<Option<Checksum>>,
+        skip_verify: bool,
+        config: &Config,
+    ) -> Result<(Vec<PathBuf>, String, Option<UpdateInfo>)> {
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || {
+            let installed = downloaded.and_then(|checksum| {
+                AppImage.install(&mut staging, checksum.as_ref(), skip_verify, &config)
+            });
+            staging.finish(installed)
+        })
+        .await?
+    }
+    /// Unpacks the AppImage into `squashfs-root` next to it without running it
+    pub fn extract(&self, file_path: &str) -> Result<()> {
+        let appimage_path = Path::new(file_path);
+        let dir_path = appimage_path.parent().unwrap();
+        SquashFs::open(appimage_path)?.extract(&dir_path.join("squashfs-root"))
+    }
+    /// Reads the desktop entry, icon and AppStream metadata straight out of
+    /// the embedded filesystem. Paths written into the desktop entry go
+    /// through the `current` link so switching versions needs no rewrite
+    pub fn integrate(&self, staging: &mut Staging, config: &Config) -> Result<Vec<PathBuf>> {
+        let name = staging.name.clone();
+        let desktop_applications_path = config.desktop_dir()?;
+        let desktop_applications_path = desktop_applications_path.as_path();
 
-        let desktop_file_name = format!("{}.desktop", name.to_lowercase());
-        let desktop_app_path = PathBuf::from(desktop_applications_path).join(desktop_file_name);
+        let appimage_path = PathBuf::from(staging.file_path());
+        let exec_path = if config.extract() {
+            staging.current().join("squashfs-root").join("AppRun")
+        } else {
+            staging.current().join(format!("{}.appimage", name))
+        };
+
+        let squashfs = SquashFs::open(&appimage_path)?;
+        let (entries, desktop_file_content) = self.desktop_entry(&squashfs)?;
 
-        fs::copy(desktop_file, desktop_app_path)?;
+        let (icon, extension) = self.icon(&squashfs, &entries, &desktop_file_content)?;
+
+        let mut files = Vec::new();
+        let icon_file_name = format!("{}.{}", name.to_lowercase(), extension);
+        let icon_target = match config.icon_dir()? {
+            Some(icon_dir) => {
+                let icon_target = icon_dir.join(&icon_file_name);
+                staging.write(&icon_target, icon)?;
+                files.push(icon_target.clone());
+                icon_target
+            }
+            None => {
+                fs::write(staging.folder.join(&icon_file_name), icon)?;
+                staging.current().join(&icon_file_name)
+            }
+        };
 
         let desktop_file_name = format!("{}.desktop", name.to_lowercase());
         let desktop_app_path = PathBuf::from(desktop_applications_path).join(desktop_file_name);
 
-        let mut desktop_file_content = std::fs::read_to_string(&desktop_app_path)?;
         let lines: Vec<_> = desktop_file_content
             .lines()
             .map(|line| {
                 if line.starts_with("Icon=") {
-                    format!("Icon={}", icon)
+                    format!("Icon={}", icon_target.display())
                 } else if line.starts_with("Exec=") {
                     format!("Exec={} %U", exec_path.display())
                 } else {
@@ -109,10 +578,164 @@ impl AppImage {
             })
             .collect();
 
-        desktop_file_content = lines.join("\n");
+        staging.write(&desktop_app_path, lines.join("\n"))?;
+        files.push(desktop_app_path);
+
+        if let Some(metainfo) = self.metainfo(&squashfs)? {
+            let metainfo_path = Paths
+                .data_home()?
+                .join("metainfo")
+                .join(format!("{}.metainfo.xml", name.to_lowercase()));
+            staging.write(&metainfo_path, metainfo)?;
+            files.push(metainfo_path);
+        }
 
-        std::fs::write(&desktop_app_path, desktop_file_content)?;
+        Ok(files)
+    }
+    /// Files at the root of the AppImage and its desktop entry
+    fn desktop_entry(&self, squashfs: &SquashFs) -> Result<(Vec<String>, String)> {
+        let entries: Vec<_> = squashfs
+            .read_dir("/")?
+            .into_iter()
+            .filter(|entry| matches!(entry.kind, EntryKind::File | EntryKind::Symlink))
+            .map(|entry| entry.name)
+            .collect();
 
-        Ok(())
+        let desktop_file = entries
+            .iter()
+            .find(|entry| entry.ends_with(".desktop"))
+            .ok_or_else(|| error!("No desktop file found"))?;
+        let desktop_file_content =
+            String::from_utf8_lossy(&squashfs.read_file(desktop_file)?).to_string();
+        Ok((entries, desktop_file_content))
+    }
+    /// The icon named by the desktop entry, or any icon at the root of the
+    /// AppImage, with its extension
+    fn icon(
+        &self,
+        squashfs: &SquashFs,
+        entries: &[String],
+        desktop_entry: &str,
+    ) -> Result<(Vec<u8>, String)> {
+        let icon_name = desktop_entry
+            .lines()
+            .find_map(|line| line.strip_prefix("Icon="))
+            .map(|icon| icon.trim().to_string());
+        let icon_extensions = ["svg", "png", "jpg", "jpeg", "bmp", "ico", "webp"];
+        let extension_of = |entry: &str| {
+            Path::new(entry)
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_lowercase()
+        };
+        let is_icon = |entry: &&String| {
+            icon_extensions
+                .iter()
+                .any(|&ext| ext == extension_of(entry))
+        };
+
+        let icon = match entries
+            .iter()
+            .filter(is_icon)
+            .find(|entry| {
+                icon_name.as_deref() == Path::new(entry).file_stem().and_then(|stem| stem.to_str())
+            })
+            .or_else(|| entries.iter().find(is_icon))
+        {
+            Some(entry) => (squashfs.read_file(entry)?, extension_of(entry)),
+            None if squashfs.exists(".DirIcon") => {
+                let data = squashfs.read_file(".DirIcon")?;
+                let extension = if data.starts_with(b"\x89PNG") {
+                    "png"
+                } else {
+                    "svg"
+                };
+                (data, extension.to_string())
+            }
+            None => return Err(error!("No icon found")),
+        };
+        Ok(icon)
+    }
+    /// Writes the icon of the AppImage at `file_path` to `icon_dir` again,
+    /// for when an older version of `name` becomes current
+    pub fn restore_icon(&self, name: &str, file_path: &Path, icon_dir: &Path) -> Result<PathBuf> {
+        let squashfs = SquashFs::open(file_path)?;
+        let (entries, desktop_file_content) = self.desktop_entry(&squashfs)?;
+        let (icon, extension) = self.icon(&squashfs, &entries, &desktop_file_content)?;
+        let icon_target = icon_dir.join(format!("{}.{}", name.to_lowercase(), extension));
+        fs::create_dir_all(icon_dir)?;
+        fs::write(&icon_target, icon)?;
+        Ok(icon_target)
+    }
+    /// AppStream metadata lives in `usr/share/metainfo`, older AppImages
+    /// still use `usr/share/appdata`
+    fn metainfo(&self, squashfs: &SquashFs) -> Result<Option<Vec<u8>>> {
+        for dir in ["usr/share/metainfo", "usr/share/appdata"] {
+            let Ok(entries) = squashfs.read_dir(dir) else {
+                continue;
+            };
+            if let Some(entry) = entries
+                .iter()
+                .find(|entry| entry.name.ends_with(".xml") && entry.kind != EntryKind::Dir)
+            {
+                return Ok(Some(
+                    squashfs.read_file(&format!("{}/{}", dir, entry.name))?,
+                ));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_replaces_the_whole_version() {
+        let pattern = asset_pattern("App-6-x86_64.AppImage", "v6");
+        assert_eq!(pattern, "App-*-x86_64.AppImage");
+        assert!(matches_pattern(&pattern, "App-7-x86_64.AppImage"));
+        assert!(!matches_pattern(&pattern, "App-7-x86_arm64.AppImage"));
+
+        assert_eq!(
+            asset_pattern("App-1.2.3-x86_64.AppImage", "v1.2.3"),
+            "App-*-x86_64.AppImage"
+        );
+        assert_eq!(
+            asset_pattern("App_v1.2_1.2.AppImage", "1.2"),
+            "App_v*_1.2.AppImage"
+        );
+    }
+
+    #[test]
+    fn pattern_without_the_tag_in_the_name() {
+        // `1.0` is only the start of `1.0.1`, the dotted run is used instead
+        assert_eq!(
+            asset_pattern("App-1.0.1-x86_64.AppImage", "1.0"),
+            "App-*-x86_64.AppImage"
+        );
+        assert_eq!(
+            asset_pattern("App-2024.01.15-x86_64.AppImage", "nightly"),
+            "App-*-x86_64.AppImage"
+        );
+        assert_eq!(asset_pattern("App.AppImage", ""), "App.AppImage");
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches_pattern(
+            "App-*-x86_64.AppImage",
+            "App-1.2-x86_64.AppImage"
+        ));
+        assert!(matches_pattern("App-*.AppImage", "App-.AppImage"));
+        assert!(matches_pattern("App-?.AppImage", "App-7.AppImage"));
+        assert!(!matches_pattern("App-?.AppImage", "App-10.AppImage"));
+        assert!(!matches_pattern(
+            "App-*-x86_64.AppImage",
+            "App-1.2-aarch64.AppImage"
+        ));
+        assert!(!matches_pattern("App-*.AppImage", "App-1.2.AppImage.zsync"));
     }
 }
@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// Architectures AppImages are commonly built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+    Armhf,
+    I386,
+}
+
+impl Arch {
+    const ALL: [Arch; 4] = [Arch::X86_64, Arch::Aarch64, Arch::Armhf, Arch::I386];
+
+    /// The architecture appi runs on
+    pub fn host() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86_64" => Some(Arch::X86_64),
+            "aarch64" => Some(Arch::Aarch64),
+            "arm" => Some(Arch::Armhf),
+            "x86" => Some(Arch::I386),
+            _ => None,
+        }
+    }
+
+    /// Reads the `e_machine` field of an ELF header
+    pub fn from_machine(machine: u16) -> Option<Self> {
+        match machine {
+            62 => Some(Arch::X86_64),
+            183 => Some(Arch::Aarch64),
+            40 => Some(Arch::Armhf),
+            3 => Some(Arch::I386),
+            _ => None,
+        }
+    }
+
+    /// The architecture named in an asset name, e.g. `App-1.0-arm64.AppImage`
+    pub fn from_name(name: &str) -> Option<Self> {
+        // `x86` alone means 32 bit, so the 64 bit spellings go first
+        let name = name
+            .to_lowercase()
+            .replace("x86_64", "amd64")
+            .replace("x86-64", "amd64");
+        let words: Vec<&str> = name
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Arch::ALL
+            .into_iter()
+            .find(|arch| arch.tokens().iter().any(|token| words.contains(token)))
+    }
+
+    fn tokens(&self) -> &'static [&'static str] {
+        match self {
+            Arch::X86_64 => &["amd64", "x64"],
+            Arch::Aarch64 => &["aarch64", "arm64", "armv8"],
+            Arch::Armhf => &["armhf", "armv7l", "armv7", "arm32", "arm"],
+            Arch::I386 => &["i386", "i686", "x86", "ia32"],
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arch::X86_64 => write!(f, "x86_64"),
+            Arch::Aarch64 => write!(f, "aarch64"),
+            Arch::Armhf => write!(f, "armhf"),
+            Arch::I386 => write!(f, "i386"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for (name, arch) in [
+            ("App-1.0-x86_64.AppImage", Some(Arch::X86_64)),
+            ("App-1.0-x64.AppImage", Some(Arch::X86_64)),
+            ("App-1.0-aarch64.AppImage", Some(Arch::Aarch64)),
+            ("App-1.0-armv7l.AppImage", Some(Arch::Armhf)),
+            ("App-1.0-arm.AppImage", Some(Arch::Armhf)),
+            ("App-1.0-i686.AppImage", Some(Arch::I386)),
+            ("App-1.0.AppImage", None),
+            ("Charm-1.0.AppImage", None),
+        ] {
+            assert_eq!(Arch::from_name(name), arch, "{}", name);
+        }
+    }
+
+    #[test]
+    fn two_architectures() {
+        // The first one in `Arch::ALL` wins, whatever the order in the name
+        assert_eq!(
+            Arch::from_name("App-arm64-x86_64.AppImage"),
+            Some(Arch::X86_64)
+        );
+        assert_eq!(
+            Arch::from_name("App-armhf-aarch64.AppImage"),
+            Some(Arch::Aarch64)
+        );
+    }
+}
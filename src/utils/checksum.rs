@@ -0,0 +1,167 @@
+use crate::utils::errors::error;
+use anyhow::Result;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::{fmt, fs, io};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::Sha1 => write!(f, "sha1"),
+            Algorithm::Sha256 => write!(f, "sha256"),
+            Algorithm::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub hex: String,
+}
+
+impl Checksum {
+    /// Guesses the algorithm from the length of a hex encoded hash
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().to_lowercase();
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let algorithm = match hex.len() {
+            40 => Algorithm::Sha1,
+            64 => Algorithm::Sha256,
+            128 => Algorithm::Sha512,
+            _ => return None,
+        };
+        Some(Checksum { algorithm, hex })
+    }
+
+    /// Parses the `digest` field of a github release asset, e.g. `sha256:<hex>`
+    pub fn from_digest(digest: &str) -> Option<Self> {
+        let (algorithm, hex) = digest.split_once(':')?;
+        let checksum = Checksum::from_hex(hex)?;
+        match (algorithm, checksum.algorithm) {
+            ("sha256", Algorithm::Sha256) | ("sha512", Algorithm::Sha512) => Some(checksum),
+            _ => None,
+        }
+    }
+
+    /// Finds the hash of `file_name` in `sums_name`, the output of
+    /// `sha256sum`/`sha512sum`. A single hash without a name only counts when
+    /// the file is named after `file_name`, e.g. `App.AppImage.sha256`
+    pub fn from_sums(content: &str, sums_name: &str, file_name: &str) -> Option<Self> {
+        let sums_name = sums_name.to_lowercase();
+        let own = [".sha256", ".sha512"]
+            .iter()
+            .any(|extension| sums_name == format!("{}{}", file_name.to_lowercase(), extension));
+        let lines: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        for line in &lines {
+            let mut parts = line.split_whitespace();
+            let (Some(hex), name) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let matches = match name {
+                Some(name) => {
+                    let name = name.trim_start_matches('*');
+                    let name = name.rsplit('/').next().unwrap_or(name);
+                    name == file_name
+                }
+                None => own && lines.len() == 1,
+            };
+            if matches {
+                return Checksum::from_hex(hex);
+            }
+        }
+        None
+    }
+
+    pub fn compute(&self, file_path: &str) -> Result<String> {
+        let mut file = fs::File::open(file_path)?;
+        let hex = match self.algorithm {
+            Algorithm::Sha1 => {
+                let mut hasher = Sha1::new();
+                io::copy(&mut file, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+            Algorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut file, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+            Algorithm::Sha512 => {
+                let mut hasher = Sha512::new();
+                io::copy(&mut file, &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+        };
+        Ok(hex)
+    }
+
+    pub fn verify(&self, file_path: &str) -> Result<()> {
+        let actual = self.compute(file_path)?;
+        if actual != self.hex {
+            return Err(error!(format!(
+                "Checksum mismatch ({}): expected {} but got {}",
+                self.algorithm, self.hex, actual
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn sums_name_the_file() {
+        let content = format!(
+            "{}  App.AppImage\n{}  *dist/Other.AppImage\n",
+            SHA256,
+            "0".repeat(64)
+        );
+        let checksum = Checksum::from_sums(&content, "SHA256SUMS", "App.AppImage").unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        assert_eq!(checksum.hex, SHA256);
+
+        let other = Checksum::from_sums(&content, "SHA256SUMS", "Other.AppImage").unwrap();
+        assert_eq!(other.hex, "0".repeat(64));
+        assert!(Checksum::from_sums(&content, "SHA256SUMS", "Missing.AppImage").is_none());
+    }
+
+    #[test]
+    fn bare_hash_of_own_file() {
+        let checksum = Checksum::from_sums(SHA256, "App.AppImage.sha256", "App.AppImage");
+        assert_eq!(
+            checksum.map(|checksum| checksum.hex),
+            Some(SHA256.to_string())
+        );
+    }
+
+    #[test]
+    fn bare_hash_of_another_file() {
+        assert!(Checksum::from_sums(SHA256, "App.tar.gz.sha256", "App.AppImage").is_none());
+        assert!(Checksum::from_sums(SHA256, "SHA256SUMS", "App.AppImage").is_none());
+    }
+
+    #[test]
+    fn digest() {
+        let digest = format!("sha256:{}", SHA256);
+        assert!(Checksum::from_digest(&digest).is_some());
+        assert!(Checksum::from_digest(&format!("sha512:{}", SHA256)).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::utils::{errors::error, manifest::Provider, paths::Paths};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions, Permissions},
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::PathBuf,
+};
+
+pub const KEYS: [&str; 11] = [
+    "appimage_dir",
+    "desktop_dir",
+    "icon_dir",
+    "provider",
+    "extract",
+    "require_signature",
+    "keep_versions",
+    "check_jobs",
+    "download_jobs",
+    "release_scan",
+    "github_token",
+];
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Root folder where every AppImage gets its own directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub appimage_dir: Option<String>,
+    /// Folder where desktop entries are written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desktop_dir: Option<String>,
+    /// Folder where icons are copied, icons stay inside the app folder if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_dir: Option<String>,
+    /// Provider used by `search` and `install` when no flag is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<Provider>,
+    /// Keep the extracted `squashfs-root` and launch `AppRun` from it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extract: Option<bool>,
+    /// Refuse to install AppImages that are not signed by a trusted key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_signature: Option<bool>,
+    /// Installed versions kept per app, including the current one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_versions: Option<usize>,
+    /// Apps checked for updates at the same time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_jobs: Option<usize>,
+    /// Updates downloaded and installed at the same time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_jobs: Option<usize>,
+    /// Recent releases searched for an AppImage when the newest one has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_scan: Option<usize>,
+    /// Token for the github api, `$GITHUB_TOKEN` and `$GH_TOKEN` take precedence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_token: Option<String>,
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        Ok(Paths.config_home()?.join("appi").join("config.toml"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Config::path()?;
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let config = toml::from_str(&content)
+            .context(error!(format!("Failed to parse config {}", path.display())))?;
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Config::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Like `gh` does for its hosts file, a stored token is kept from
+        // other users
+        let private = self.github_token.is_some();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(if private { 0o600 } else { 0o644 })
+            .open(&path)?;
+        if private {
+            // The file may predate the token
+            file.set_permissions(Permissions::from_mode(0o600))?;
+        }
+        file.write_all(toml::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn appimage_dir(&self) -> Result<PathBuf> {
+        match &self.appimage_dir {
+            Some(dir) => Paths.expand(dir),
+            None => Ok(Paths.home()?.join("Applications")),
+        }
+    }
+
+    pub fn desktop_dir(&self) -> Result<PathBuf> {
+        match &self.desktop_dir {
+            Some(dir) => Paths.expand(dir),
+            None => Ok(Paths.data_home()?.join("applications")),
+        }
+    }
+
+    pub fn icon_dir(&self) -> Result<Option<PathBuf>> {
+        self.icon_dir
+            .as_deref()
+            .map(|dir| Paths.expand(dir))
+            .transpose()
+    }
+
+    pub fn provider(&self) -> Provider {
+        self.provider.unwrap_or(Provider::Aur)
+    }
+
+    pub fn extract(&self) -> bool {
+        self.extract.unwrap_or(true)
+    }
+
+    pub fn require_signature(&self) -> bool {
+        self.require_signature.unwrap_or(false)
+    }
+
+    pub fn keep_versions(&self) -> usize {
+        self.keep_versions.unwrap_or(2).max(1)
+    }
+
+    pub fn check_jobs(&self) -> usize {
+        self.check_jobs.unwrap_or(8).max(1)
+    }
+
+    pub fn download_jobs(&self) -> usize {
+        self.download_jobs.unwrap_or(2).max(1)
+    }
+
+    pub fn release_scan(&self) -> usize {
+        self.release_scan.unwrap_or(10).max(1)
+    }
+
+    pub fn get(&self, key: &str) -> Result<String> {
+        let value = match key {
+            "appimage_dir" => self.appimage_dir()?.display().to_string(),
+            "desktop_dir" => self.desktop_dir()?.display().to_string(),
+            "icon_dir" => self
+                .icon_dir()?
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            "provider" => self.provider().to_string(),
+            "extract" => self.extract().to_string(),
+            "require_signature" => self.require_signature().to_string(),
+            "keep_versions" => self.keep_versions().to_string(),
+            "check_jobs" => self.check_jobs().to_string(),
+            "download_jobs" => self.download_jobs().to_string(),
+            "release_scan" => self.release_scan().to_string(),
+            // Not printed in full so it does not end up in terminal logs
+            "github_token" => self
+                .github_token
+                .as_ref()
+                .map(|token| format!("{}…", token.chars().take(4).collect::<String>()))
+                .unwrap_or_default(),
+            _ => return Err(Config::unknown_key(key)),
+        };
+        Ok(value)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "appimage_dir" => self.appimage_dir = Some(value.to_string()),
+            "desktop_dir" => self.desktop_dir = Some(value.to_string()),
+            "icon_dir" => self.icon_dir = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "github_token" => {
+                self.github_token = Some(value.trim().to_string()).filter(|v| !v.is_empty())
+            }
+            "provider" => {
+                self.provider = Some(match value.to_lowercase().as_str() {
+                    "aur" => Provider::Aur,
+                    "github" => Provider::Github,
+                    _ => return Err(error!("provider must be one of: aur, github")),
+                })
+            }
+            "extract" => {
+                self.extract = Some(
+                    value
+                        .parse()
+                        .context(error!("extract must be true or false"))?,
+                )
+            }
+            "require_signature" => {
+                self.require_signature = Some(
+                    value
+                        .parse()
+                        .context(error!("require_signature must be true or false"))?,
+                )
+            }
+            "keep_versions" => {
+                self.keep_versions = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|&keep| keep > 0)
+                        .context(error!("keep_versions must be a positive number"))?,
+                )
+            }
+            "check_jobs" | "download_jobs" | "release_scan" => {
+                let count = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .context(error!(format!("{} must be a positive number", key)))?,
+                );
+                match key {
+                    "check_jobs" => self.check_jobs = count,
+                    "download_jobs" => self.download_jobs = count,
+                    _ => self.release_scan = count,
+                }
+            }
+            _ => return Err(Config::unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn unknown_key(key: &str) -> anyhow::Error {
+        error!(format!(
+            "Unknown config key {}, expected one of: {}",
+            key,
+            KEYS.join(", ")
+        ))
+    }
+}
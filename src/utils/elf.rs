@@ -0,0 +1,303 @@
+use crate::utils::errors::error;
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Minimal ELF reader for the runtime that prefixes every type-2 AppImage
+#[derive(Debug, Clone)]
+pub struct Elf {
+    pub path: PathBuf,
+    /// Length of the file, header fields pointing past it are rejected
+    pub len: u64,
+    pub is_64: bool,
+    pub little_endian: bool,
+    pub machine: u16,
+    pub section_header_offset: u64,
+    pub section_header_size: u16,
+    pub section_header_count: u16,
+    pub sections: Vec<Section>,
+}
+
+impl Elf {
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut ident = [0u8; 16];
+        file.read_exact(&mut ident)?;
+        if &ident[..4] != b"\x7fELF" {
+            return Err(error!(format!("{} is not an ELF file", path.display())));
+        }
+        let is_64 = match ident[4] {
+            1 => false,
+            2 => true,
+            _ => return Err(error!("Unknown ELF class")),
+        };
+        let little_endian = match ident[5] {
+            1 => true,
+            2 => false,
+            _ => return Err(error!("Unknown ELF byte order")),
+        };
+
+        let header_size = if is_64 { 64 } else { 52 };
+        let mut header = vec![0u8; header_size];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+
+        let mut elf = Elf {
+            path: path.to_path_buf(),
+            len,
+            is_64,
+            little_endian,
+            machine: 0,
+            section_header_offset: 0,
+            section_header_size: 0,
+            section_header_count: 0,
+            sections: Vec::new(),
+        };
+
+        elf.machine = elf.u16(&header[18..]);
+        let (shoff, rest) = if is_64 {
+            (elf.u64(&header[40..]), &header[58..])
+        } else {
+            (elf.u32(&header[32..]) as u64, &header[46..])
+        };
+        elf.section_header_offset = shoff;
+        elf.section_header_size = elf.u16(rest);
+        elf.section_header_count = elf.u16(&rest[2..]);
+        let string_table_index = elf.u16(&rest[4..]) as usize;
+
+        if shoff == 0 || elf.section_header_count == 0 {
+            return Ok(elf);
+        }
+
+        let entry_size = if is_64 { 64 } else { 40 };
+        if (elf.section_header_size as usize) < entry_size {
+            return Err(error!(format!(
+                "{} has section headers of {} bytes, expected at least {}",
+                path.display(),
+                elf.section_header_size,
+                entry_size
+            )));
+        }
+        let table_size = elf.section_header_size as u64 * elf.section_header_count as u64;
+        elf.check_bounds(shoff, table_size)?;
+        let mut table = vec![0u8; table_size as usize];
+        file.seek(SeekFrom::Start(shoff))?;
+        file.read_exact(&mut table)?;
+
+        let headers: Vec<(u32, u64, u64)> = table
+            .chunks(elf.section_header_size as usize)
+            .map(|entry| {
+                let name = elf.u32(entry);
+                if is_64 {
+                    (name, elf.u64(&entry[24..]), elf.u64(&entry[32..]))
+                } else {
+                    (
+                        name,
+                        elf.u32(&entry[16..]) as u64,
+                        elf.u32(&entry[20..]) as u64,
+                    )
+                }
+            })
+            .collect();
+
+        let Some(&(_, names_offset, names_size)) = headers.get(string_table_index) else {
+            return Ok(elf);
+        };
+        elf.check_bounds(names_offset, names_size)?;
+        let mut names = vec![0u8; names_size as usize];
+        file.seek(SeekFrom::Start(names_offset))?;
+        file.read_exact(&mut names)?;
+
+        elf.sections = headers
+            .iter()
+            .map(|&(name, offset, size)| {
+                let name = names
+                    .get(name as usize..)
+                    .and_then(|bytes| bytes.split(|&b| b == 0).next())
+                    .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+                    .unwrap_or_default();
+                Section { name, offset, size }
+            })
+            .collect();
+        Ok(elf)
+    }
+
+    /// Reads `e_machine` from the first bytes of a file, enough to tell the
+    /// architecture of an AppImage before downloading all of it
+    pub fn machine(header: &[u8]) -> Option<u16> {
+        if header.get(..4)? != b"\x7fELF" {
+            return None;
+        }
+        let bytes = [*header.get(18)?, *header.get(19)?];
+        match header[5] {
+            2 => Some(u16::from_be_bytes(bytes)),
+            _ => Some(u16::from_le_bytes(bytes)),
+        }
+    }
+
+    /// Where the ELF runtime ends and the appended filesystem starts
+    pub fn size(&self) -> u64 {
+        self.section_header_offset
+            + self.section_header_size as u64 * self.section_header_count as u64
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    pub fn read_section(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(section) = self.section(name) else {
+            return Ok(None);
+        };
+        self.check_bounds(section.offset, section.size)?;
+        let mut file = File::open(&self.path)?;
+        let mut data = vec![0u8; section.size as usize];
+        file.seek(SeekFrom::Start(section.offset))?;
+        file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Refuses `size` bytes at `offset` unless they lie within the file, so a
+    /// corrupt header cannot make us allocate more than the file holds
+    fn check_bounds(&self, offset: u64, size: u64) -> Result<()> {
+        if offset.checked_add(size).is_none_or(|end| end > self.len) {
+            return Err(error!(format!(
+                "{} is corrupt, its ELF header points past the end of the file",
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn u64(&self, bytes: &[u8]) -> u64 {
+        let mut array = [0u8; 8];
+        array.copy_from_slice(&bytes[..8]);
+        if self.little_endian {
+            u64::from_le_bytes(array)
+        } else {
+            u64::from_be_bytes(array)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    const NAMES: &[u8] = b"\0.shstrtab\0.upd_info\0";
+    const TABLE: u64 = 64 + NAMES.len() as u64 + 5;
+
+    /// A 64 bit runtime with a string table and an `.upd_info` section
+    fn runtime() -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+        data[18..20].copy_from_slice(&62u16.to_le_bytes());
+        data[40..48].copy_from_slice(&TABLE.to_le_bytes());
+        data[58..60].copy_from_slice(&64u16.to_le_bytes());
+        data[60..62].copy_from_slice(&3u16.to_le_bytes());
+        data[62..64].copy_from_slice(&1u16.to_le_bytes());
+        data.extend(NAMES);
+        data.extend(b"hello");
+
+        data.extend([0u8; 64]);
+        for (name, offset, size) in [(1u32, 64u64, NAMES.len() as u64), (11, TABLE - 5, 5)] {
+            let mut entry = [0u8; 64];
+            entry[..4].copy_from_slice(&name.to_le_bytes());
+            entry[24..32].copy_from_slice(&offset.to_le_bytes());
+            entry[32..40].copy_from_slice(&size.to_le_bytes());
+            data.extend(entry);
+        }
+        data
+    }
+
+    fn write(data: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(data).unwrap();
+        file
+    }
+
+    fn read(data: &[u8]) -> Result<Elf> {
+        Elf::read(write(data).path())
+    }
+
+    #[test]
+    fn sections() {
+        let data = runtime();
+        let file = write(&data);
+        let elf = Elf::read(file.path()).unwrap();
+        assert_eq!(elf.machine, 62);
+        assert_eq!(elf.size(), data.len() as u64);
+        let names: Vec<_> = elf.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["", ".shstrtab", ".upd_info"]);
+        assert_eq!(elf.read_section(".upd_info").unwrap().unwrap(), b"hello");
+        assert_eq!(elf.read_section(".sha256_sig").unwrap(), None);
+    }
+
+    #[test]
+    fn not_elf() {
+        assert!(read(b"").is_err());
+        assert!(read(b"#!/bin/sh\necho hello\n").is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = runtime();
+        for len in [10, 40, TABLE as usize + 10, data.len() - 1] {
+            assert!(read(&data[..len]).is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let mut data = runtime();
+        data[58..60].copy_from_slice(&0u16.to_le_bytes());
+        assert!(read(&data).is_err());
+
+        let mut data = runtime();
+        data[40..48].copy_from_slice(&(u64::MAX - 10).to_le_bytes());
+        assert!(read(&data).is_err());
+
+        let mut data = runtime();
+        let names_size = TABLE as usize + 64 + 32;
+        data[names_size..names_size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read(&data).is_err());
+
+        let mut data = runtime();
+        let section_size = TABLE as usize + 128 + 32;
+        data[section_size..section_size + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let file = write(&data);
+        let elf = Elf::read(file.path()).unwrap();
+        assert!(elf.read_section(".upd_info").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    process::exit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// Downloads still writing their partial file
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+fn sender() -> &'static watch::Sender<bool> {
+    static SENDER: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    SENDER.get_or_init(|| watch::channel(false).0)
+}
+
+/// Listens for Ctrl-C once for the whole program. Downloads in progress are
+/// told to stop and keep what they fetched, then the program exits as it
+/// would without a handler
+pub fn listen() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        sender().send_replace(true);
+        for _ in 0..50 {
+            if ACTIVE.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        exit(130)
+    });
+}
+
+/// Held by a download while it streams, see [`listen`]
+pub struct Guard {
+    receiver: watch::Receiver<bool>,
+}
+
+pub fn guard() -> Guard {
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    Guard {
+        receiver: sender().subscribe(),
+    }
+}
+
+impl Guard {
+    /// Resolves once Ctrl-C was pressed
+    pub async fn interrupted(&mut self) {
+        let _ = self.receiver.wait_for(|&interrupted| interrupted).await;
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,390 @@
+use crate::utils::{
+    appimage::{AppImage, UpdateInfo},
+    config::Config,
+    errors::error,
+    paths::Paths,
+    version::TagFilter,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Github,
+    Aur,
+    /// Installed from a file on disk
+    Local,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provider::Github => write!(f, "github"),
+            Provider::Aur => write!(f, "aur"),
+            Provider::Local => write!(f, "local"),
+        }
+    }
+}
+
+/// Keeps `update` from moving an app past a version
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Pin {
+    /// Never updated
+    Hold,
+    /// Only updated to versions matching a semver requirement such as `~1.4`
+    Requirement(String),
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pin::Hold => write!(f, "pinned"),
+            Pin::Requirement(requirement) => write!(f, "pinned to {}", requirement),
+        }
+    }
+}
+
+/// Which releases of a github app count as its latest one
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// The release github marks as latest
+    #[default]
+    Stable,
+    /// The newest release, pre-releases included
+    Prerelease,
+    /// The newest nightly or continuous build
+    Nightly,
+    /// The newest release whose tag matches a regex
+    Tag(String),
+}
+
+impl Channel {
+    pub fn is_stable(&self) -> bool {
+        *self == Channel::Stable
+    }
+}
+
+impl FromStr for Channel {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "stable" => Ok(Channel::Stable),
+            "prerelease" => Ok(Channel::Prerelease),
+            "nightly" => Ok(Channel::Nightly),
+            _ => {
+                let pattern = value.strip_prefix("tag:").context(error!(
+                    "channel must be one of: stable, prerelease, nightly, tag:<regex>"
+                ))?;
+                Regex::new(pattern).context(error!(format!("{} is not a valid regex", pattern)))?;
+                Ok(Channel::Tag(pattern.to_string()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Prerelease => write!(f, "prerelease"),
+            Channel::Nightly => write!(f, "nightly"),
+            Channel::Tag(pattern) => write!(f, "tag:{}", pattern),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Installed {
+    pub name: String,
+    pub provider: Provider,
+    /// Repository owner on github, `None` for AUR packages
+    pub owner: Option<String>,
+    /// Repository name on github or package name on the AUR
+    pub repo: String,
+    pub tag: String,
+    pub version: String,
+    pub asset: String,
+    pub url: String,
+    pub installed_at: DateTime<Utc>,
+    pub files: Vec<PathBuf>,
+    pub digest: String,
+    /// Update source declared by the AppImage itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_info: Option<UpdateInfo>,
+    /// Folder of this version inside the app folder, `None` for apps
+    /// installed before versions were kept side by side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<PathBuf>,
+    /// Earlier versions still on disk, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous: Vec<Installed>,
+    /// Installed at a release picked by the user, `update` leaves it alone
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub chosen: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<Pin>,
+    /// Glob picking the asset to install when a release has several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Channel::is_stable")]
+    pub channel: Channel,
+    /// Releases of this app in a repository releasing several products
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_filter: Option<TagFilter>,
+    /// AppImage of an app imported from before the manifest existed, its
+    /// folder and file are named after the repository rather than the app
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legacy_path: Option<PathBuf>,
+}
+
+impl Installed {
+    /// An app installed just now, providers fill in what they know about it
+    pub fn new(name: &str, provider: Provider, repo: &str) -> Self {
+        Installed {
+            name: name.to_string(),
+            provider,
+            owner: None,
+            repo: repo.to_string(),
+            tag: String::new(),
+            version: String::new(),
+            asset: String::new(),
+            url: String::new(),
+            installed_at: Utc::now(),
+            files: Vec::new(),
+            digest: String::new(),
+            update_info: None,
+            folder: None,
+            previous: Vec::new(),
+            chosen: false,
+            pin: None,
+            asset_pattern: None,
+            channel: Channel::Stable,
+            tag_filter: None,
+            legacy_path: None,
+        }
+    }
+
+    /// Github repository the app is released from, declared by the AppImage
+    /// or the one it was installed from
+    pub fn github_repo(&self) -> Option<(&str, &str)> {
+        match (&self.update_info, self.provider, &self.owner) {
+            (Some(UpdateInfo::GhReleases { owner, repo, .. }), _, _) => Some((owner, repo)),
+            (_, Provider::Github, Some(owner)) => Some((owner, &self.repo)),
+            _ => None,
+        }
+    }
+
+    /// `owner/repo` for github apps, the package name for AUR apps
+    pub fn source(&self) -> String {
+        match &self.owner {
+            Some(owner) => format!("{}/{}", owner, self.repo),
+            None => self.repo.clone(),
+        }
+    }
+
+    pub fn file_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.legacy_path {
+            return Ok(path.clone());
+        }
+        if let Some(folder) = &self.folder {
+            return Ok(folder.join(format!("{}.appimage", self.name)));
+        }
+        Ok(Config::load()?
+            .appimage_dir()?
+            .join(&self.name)
+            .join(format!("{}.appimage", self.name)))
+    }
+
+    /// Removes integration files of this version that `current` no longer uses
+    pub fn remove_stale_files(&self, current: &Installed) -> Result<()> {
+        for path in self
+            .files
+            .iter()
+            .filter(|path| !current.files.contains(path))
+        {
+            if path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_files(&self) -> Result<()> {
+        for path in &self.files {
+            if path.is_dir() {
+                fs::remove_dir_all(path)?;
+            } else if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Held while concurrent installs load, change and save the manifest
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Manifest {
+    pub apps: BTreeMap<String, Installed>,
+}
+
+impl Manifest {
+    pub fn path() -> Result<PathBuf> {
+        Ok(Paths.data_home()?.join("appi").join("installed.json"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Manifest::path()?;
+        if !path.exists() {
+            let manifest = Manifest::import_legacy()?;
+            if !manifest.apps.is_empty() {
+                manifest.save()?;
+            }
+            return Ok(manifest);
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let manifest = serde_json::from_str(&content).context(error!(format!(
+            "Failed to parse manifest {}",
+            path.display()
+        )))?;
+        Ok(manifest)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Manifest::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Loads the manifest, applies `change` and saves it, so installs running
+    /// at the same time do not drop each other's changes
+    pub fn update<T>(change: impl FnOnce(&mut Manifest) -> Result<T>) -> Result<T> {
+        let _lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut manifest = Manifest::load()?;
+        let value = change(&mut manifest)?;
+        manifest.save()?;
+        Ok(value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Installed> {
+        self.apps.get(name)
+    }
+
+    pub fn insert(&mut self, app: Installed) {
+        self.apps.insert(app.name.clone(), app);
+    }
+
+    /// Records a new version of an app as current, keeping up to `keep`
+    /// versions and removing the folders of older ones
+    pub fn insert_version(&mut self, mut app: Installed, keep: usize) -> Result<()> {
+        if let Some(mut old) = self.remove(&app.name) {
+            app.pin = old.pin.take();
+            let mut previous = std::mem::take(&mut old.previous);
+            if old.folder.is_some() {
+                previous.push(old);
+            } else if let Some(legacy_folder) = old.legacy_path.as_deref().and_then(Path::parent) {
+                // Unless it is the app folder itself, staging does not see it
+                let app_folder = app.folder.as_deref().and_then(Path::parent);
+                if app_folder != Some(legacy_folder) && legacy_folder.is_dir() {
+                    fs::remove_dir_all(legacy_folder)?;
+                }
+            }
+            previous.retain(|version| version.folder != app.folder);
+
+            let excess = previous.len().saturating_sub(keep.saturating_sub(1));
+            for dropped in previous.drain(..excess) {
+                if let Some(folder) = dropped.folder.filter(|folder| folder.is_dir()) {
+                    fs::remove_dir_all(folder)?;
+                }
+            }
+            app.previous = previous;
+        }
+        self.insert(app);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Installed> {
+        self.apps.remove(name)
+    }
+
+    /// Builds a manifest from AppImages installed before the manifest existed,
+    /// whose metadata was encoded as `name-creator-vVERSION.appimage`
+    fn import_legacy() -> Result<Self> {
+        let mut manifest = Manifest::default();
+        let config = Config::load()?;
+        let base_path = config.appimage_dir()?;
+        if !base_path.is_dir() {
+            return Ok(manifest);
+        }
+
+        let desktop_path = config.desktop_dir()?;
+
+        for repo_entry in fs::read_dir(&base_path)? {
+            let repo_path = repo_entry?.path();
+            if !repo_path.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&repo_path)? {
+                let path = entry?.path();
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                if !path.is_file() || !file_name.to_lowercase().ends_with(".appimage") {
+                    continue;
+                }
+
+                let stem = &file_name[..file_name.len() - ".appimage".len()];
+                let mut parts = stem.rsplitn(3, '-');
+                let (Some(version), Some(creator), Some(name)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    continue;
+                };
+
+                let version = version.trim_start_matches('v').to_string();
+                let name = name.to_lowercase();
+                let (provider, owner) = if creator.eq_ignore_ascii_case("aur") {
+                    (Provider::Aur, None)
+                } else {
+                    (Provider::Github, Some(creator.replace('_', "-")))
+                };
+
+                let files = vec![
+                    repo_path.clone(),
+                    desktop_path.join(format!("{}.desktop", name)),
+                ];
+
+                manifest.insert(Installed {
+                    owner,
+                    tag: version.clone(),
+                    version,
+                    asset: file_name.to_string(),
+                    files,
+                    digest: AppImage.digest(&path.to_string_lossy()).unwrap_or_default(),
+                    update_info: AppImage.update_info(&path.to_string_lossy()).ok().flatten(),
+                    legacy_path: Some(path.clone()),
+                    ..Installed::new(&name, provider, &name.replace('_', "-"))
+                });
+            }
+        }
+        Ok(manifest)
+    }
+}
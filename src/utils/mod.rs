@@ -1,4 +1,18 @@
 pub mod appimage;
+pub mod arch;
+pub mod checksum;
 pub mod completions;
+pub mod config;
+pub mod elf;
 pub mod errors;
+pub mod interrupt;
+pub mod manifest;
+pub mod paths;
+pub mod progress;
+pub mod signature;
+pub mod squashfs;
+pub mod staging;
+pub mod token;
 pub mod tools;
+pub mod version;
+pub mod zsync;
@@ -0,0 +1,47 @@
+use crate::utils::errors::error;
+use anyhow::Result;
+use std::{env, path::PathBuf};
+
+/// Resolves user directories from `$HOME` and the XDG base directory variables,
+/// falling back to the defaults of the XDG base directory specification
+pub struct Paths;
+
+impl Paths {
+    pub fn home(&self) -> Result<PathBuf> {
+        env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+            .ok_or_else(|| error!("Could not determine the home directory, $HOME is not set"))
+    }
+
+    pub fn data_home(&self) -> Result<PathBuf> {
+        self.xdg("XDG_DATA_HOME", ".local/share")
+    }
+
+    pub fn config_home(&self) -> Result<PathBuf> {
+        self.xdg("XDG_CONFIG_HOME", ".config")
+    }
+
+    pub fn cache_home(&self) -> Result<PathBuf> {
+        self.xdg("XDG_CACHE_HOME", ".cache")
+    }
+
+    /// Expands a leading `~` to the home directory
+    pub fn expand(&self, path: &str) -> Result<PathBuf> {
+        if path == "~" {
+            return self.home();
+        }
+        match path.strip_prefix("~/") {
+            Some(rest) => Ok(self.home()?.join(rest)),
+            None => Ok(PathBuf::from(path)),
+        }
+    }
+
+    /// The spec requires relative paths in these variables to be ignored
+    fn xdg(&self, var: &str, default: &str) -> Result<PathBuf> {
+        match env::var_os(var).map(PathBuf::from) {
+            Some(path) if path.is_absolute() => Ok(path),
+            _ => Ok(self.home()?.join(default)),
+        }
+    }
+}
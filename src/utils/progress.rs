@@ -0,0 +1,23 @@
+use indicatif::{MultiProgress, ProgressBar};
+use std::sync::OnceLock;
+
+/// Bars of concurrent checks and downloads are drawn together so they do not
+/// overwrite each other
+pub fn bars() -> &'static MultiProgress {
+    static BARS: OnceLock<MultiProgress> = OnceLock::new();
+    BARS.get_or_init(MultiProgress::new)
+}
+
+pub fn add(pb: ProgressBar) -> ProgressBar {
+    bars().add(pb)
+}
+
+/// Prints a line above the bars, or plainly when they are not drawn
+pub fn println(line: impl AsRef<str>) {
+    match bars().is_hidden() {
+        true => println!("{}", line.as_ref()),
+        false => {
+            let _ = bars().println(line);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::utils::{elf::Elf, paths::Paths};
+use anyhow::Result;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const SIGNATURE_SECTION: &str = ".sha256_sig";
+const KEY_SECTION: &str = ".sig_key";
+const MD5_SECTION: &str = ".digest_md5";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Signature {
+    /// No signature embedded in the AppImage
+    Unsigned,
+    /// Valid signature made by a key from the trusted-keys store
+    Signed { fingerprint: String },
+    /// Valid signature made by a key that is not in the trusted-keys store
+    Untrusted { fingerprint: String },
+    /// Signature present but it could not be checked
+    Unverified(String),
+    /// MD5 digest mismatch or a signature failing against its embedded key
+    Invalid(String),
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signature::Unsigned => write!(f, "unsigned"),
+            Signature::Signed { fingerprint } => write!(f, "signed by {}", fingerprint),
+            Signature::Untrusted { fingerprint } => {
+                write!(f, "signed by untrusted key {}", fingerprint)
+            }
+            Signature::Unverified(reason) => write!(f, "unverified signature: {}", reason),
+            Signature::Invalid(reason) => write!(f, "invalid signature: {}", reason),
+        }
+    }
+}
+
+impl Signature {
+    /// Folder holding the armored public keys the user trusts
+    pub fn trusted_keys_dir() -> Result<PathBuf> {
+        Ok(Paths.config_home()?.join("appi").join("trusted-keys"))
+    }
+
+    /// Checks the embedded MD5 digest and GPG signature of a type-2 AppImage
+    /// the same way `appimagetool` computes them
+    pub fn check(file_path: &Path) -> Result<Signature> {
+        let elf = Elf::read(file_path)?;
+
+        if let Some(expected) = elf.read_section(MD5_SECTION)? {
+            if expected.iter().any(|&b| b != 0) {
+                let actual =
+                    Signature::hash::<Md5>(&elf, &[MD5_SECTION, SIGNATURE_SECTION, KEY_SECTION])?;
+                if actual[..] != expected[..actual.len().min(expected.len())] {
+                    return Ok(Signature::Invalid("MD5 digest mismatch".to_string()));
+                }
+            }
+        }
+
+        let signature = Signature::trim(elf.read_section(SIGNATURE_SECTION)?);
+        if signature.is_empty() {
+            return Ok(Signature::Unsigned);
+        }
+        let key = Signature::trim(elf.read_section(KEY_SECTION)?);
+        if Command::new("gpg").arg("--version").output().is_err() {
+            return Ok(Signature::Unverified("gpg is not installed".to_string()));
+        }
+
+        let workdir = tempfile::tempdir()?;
+        let signature_path = workdir.path().join("signature.asc");
+        fs::write(&signature_path, &signature)?;
+
+        // appimagetool signs the hex encoded digest rather than the file itself.
+        // Depending on the version the MD5 digest is embedded before or after
+        // signing, so both variants are accepted
+        let mut digest_paths = Vec::new();
+        for (index, skip) in [
+            &[SIGNATURE_SECTION, KEY_SECTION][..],
+            &[SIGNATURE_SECTION, KEY_SECTION, MD5_SECTION][..],
+        ]
+        .iter()
+        .enumerate()
+        {
+            let digest = hex::encode(Signature::hash::<Sha256>(&elf, skip)?);
+            let digest_path = workdir.path().join(format!("digest{}", index));
+            fs::write(&digest_path, digest)?;
+            digest_paths.push(digest_path);
+        }
+
+        let trusted_keys: Vec<PathBuf> = match fs::read_dir(Signature::trusted_keys_dir()?) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        if let Some(fingerprint) = Signature::gpg_verify(
+            &workdir.path().join("trusted"),
+            &trusted_keys,
+            &signature_path,
+            &digest_paths,
+        )? {
+            return Ok(Signature::Signed { fingerprint });
+        }
+
+        // Older appimagetool versions do not embed the key, without it the
+        // signature cannot be told apart from one by an unknown key
+        if key.is_empty() {
+            return Ok(Signature::Unverified(
+                "no trusted key matches and the AppImage does not embed its key".to_string(),
+            ));
+        }
+        let key_path = workdir.path().join("key.asc");
+        fs::write(&key_path, &key)?;
+        match Signature::gpg_verify(
+            &workdir.path().join("embedded"),
+            &[key_path],
+            &signature_path,
+            &digest_paths,
+        )? {
+            Some(fingerprint) => Ok(Signature::Untrusted { fingerprint }),
+            None => Ok(Signature::Invalid(
+                "signature does not match the AppImage".to_string(),
+            )),
+        }
+    }
+
+    /// Returns the fingerprint of the signing key when the signature is valid
+    fn gpg_verify(
+        home: &Path,
+        keys: &[PathBuf],
+        signature_path: &Path,
+        digest_paths: &[PathBuf],
+    ) -> Result<Option<String>> {
+        fs::create_dir_all(home)?;
+        fs::set_permissions(home, fs::Permissions::from_mode(0o700))?;
+
+        if !keys.is_empty() {
+            Command::new("gpg")
+                .arg("--homedir")
+                .arg(home)
+                .args(["--batch", "--quiet", "--import"])
+                .args(keys)
+                .output()?;
+        }
+
+        for digest_path in digest_paths {
+            let output = Command::new("gpg")
+                .arg("--homedir")
+                .arg(home)
+                .args(["--batch", "--status-fd", "1", "--verify"])
+                .arg(signature_path)
+                .arg(digest_path)
+                .output()?;
+
+            let status = String::from_utf8_lossy(&output.stdout);
+            let fingerprint = status.lines().find_map(|line| {
+                line.strip_prefix("[GNUPG:] VALIDSIG ")
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .map(str::to_string)
+            });
+            if fingerprint.is_some() {
+                return Ok(fingerprint);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Hashes the whole file, reading the `skip` sections as zeros
+    fn hash<D: Digest>(elf: &Elf, skip: &[&str]) -> Result<Vec<u8>> {
+        let ranges: Vec<(u64, u64)> = skip
+            .iter()
+            .filter_map(|name| elf.section(name))
+            .map(|section| (section.offset, section.offset + section.size))
+            .collect();
+
+        let mut file = fs::File::open(&elf.path)?;
+        let mut hasher = D::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut position = 0u64;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            let chunk = &mut buffer[..read];
+            for &(start, end) in &ranges {
+                let from = start.max(position);
+                let to = end.min(position + read as u64);
+                if from < to {
+                    chunk[(from - position) as usize..(to - position) as usize].fill(0);
+                }
+            }
+            hasher.update(&*chunk);
+            position += read as u64;
+        }
+        Ok(hasher.finalize().to_vec())
+    }
+
+    fn trim(data: Option<Vec<u8>>) -> Vec<u8> {
+        let mut data = data.unwrap_or_default();
+        while data.last() == Some(&0) {
+            data.pop();
+        }
+        data
+    }
+}
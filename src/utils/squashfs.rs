@@ -0,0 +1,683 @@
+use crate::utils::{elf::Elf, errors::error};
+use anyhow::{Context, Result};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs::{self, File, Permissions},
+    io::{Read, Write},
+    os::unix::{
+        fs::{symlink, FileExt},
+        prelude::PermissionsExt,
+    },
+    path::Path,
+    rc::Rc,
+};
+
+const MAGIC: u32 = 0x7371_7368;
+const METADATA_SIZE: usize = 8192;
+const NO_FRAGMENT: u32 = 0xFFFF_FFFF;
+const UNCOMPRESSED_BLOCK: u32 = 1 << 24;
+const UNCOMPRESSED_METADATA: u16 = 1 << 15;
+const MAX_SYMLINKS: usize = 40;
+
+/// Decompressed metadata block and the position of the following one
+type MetadataBlock = Rc<(Vec<u8>, u64)>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Lz4,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryKind {
+    Dir,
+    File,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub kind: EntryKind,
+    inode: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Dir {
+        block: u32,
+        offset: u16,
+        size: u32,
+    },
+    File {
+        blocks_start: u64,
+        size: u64,
+        fragment: u32,
+        fragment_offset: u32,
+        block_sizes: Vec<u32>,
+    },
+    Symlink(String),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    mode: u16,
+    kind: Kind,
+}
+
+/// Reads the squashfs filesystem appended to a type-2 AppImage without
+/// executing the runtime
+pub struct SquashFs {
+    file: File,
+    offset: u64,
+    compression: Compression,
+    block_size: u32,
+    root_inode: u64,
+    inode_table: u64,
+    directory_table: u64,
+    fragment_table: u64,
+    fragment_count: u32,
+    metadata: RefCell<HashMap<u64, MetadataBlock>>,
+}
+
+/// Sequential reader over metadata blocks
+struct Cursor<'a> {
+    fs: &'a SquashFs,
+    block: u64,
+    offset: usize,
+}
+
+impl Cursor<'_> {
+    fn read(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len.min(METADATA_SIZE));
+        while data.len() < len {
+            let block = self.fs.metadata_block(self.block)?;
+            let (content, next) = (&block.0, block.1);
+            if self.offset >= content.len() {
+                self.block = next;
+                self.offset = 0;
+                continue;
+            }
+            let take = (len - data.len()).min(content.len() - self.offset);
+            data.extend_from_slice(&content[self.offset..self.offset + take]);
+            self.offset += take;
+        }
+        Ok(data)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read(8)?.try_into().unwrap()))
+    }
+}
+
+impl SquashFs {
+    /// Opens the filesystem embedded in an AppImage, right after its ELF runtime
+    pub fn open(path: &Path) -> Result<Self> {
+        let offset = Elf::read(path)?.size();
+        SquashFs::open_at(path, offset)
+    }
+
+    pub fn open_at(path: &Path, offset: u64) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut superblock = [0u8; 96];
+        file.read_exact_at(&mut superblock, offset)
+            .context(error!("No squashfs filesystem found"))?;
+
+        let u16_at = |at: usize| u16::from_le_bytes([superblock[at], superblock[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(superblock[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(superblock[at..at + 8].try_into().unwrap());
+
+        if u32_at(0) != MAGIC {
+            return Err(error!("No squashfs filesystem found"));
+        }
+        if u16_at(28) != 4 {
+            return Err(error!("Only squashfs 4.0 is supported"));
+        }
+        let compression = match u16_at(20) {
+            1 => Compression::Gzip,
+            4 => Compression::Xz,
+            5 => Compression::Lz4,
+            6 => Compression::Zstd,
+            other => {
+                return Err(error!(format!(
+                    "Unsupported squashfs compression (id {})",
+                    other
+                )))
+            }
+        };
+
+        // Anything else would divide by zero or size buffers after a corrupt
+        // header, mksquashfs only writes powers of two from 4 KiB to 1 MiB
+        let block_size = u32_at(12);
+        if !block_size.is_power_of_two() || !(4096..=1 << 20).contains(&block_size) {
+            return Err(error!(format!(
+                "Invalid squashfs block size {}",
+                block_size
+            )));
+        }
+
+        // Table positions are relative to the superblock and must lie within
+        // the bytes the filesystem says it uses
+        let bytes_used = u64_at(40);
+        let available = file.metadata()?.len().saturating_sub(offset);
+        let fragments = u32_at(16) > 0;
+        if bytes_used > available
+            || u64_at(64) >= bytes_used
+            || u64_at(72) >= bytes_used
+            || (fragments && u64_at(80) >= bytes_used)
+        {
+            return Err(error!("Truncated or corrupt squashfs filesystem"));
+        }
+
+        Ok(SquashFs {
+            file,
+            offset,
+            compression,
+            block_size,
+            fragment_count: u32_at(16),
+            root_inode: u64_at(32),
+            inode_table: u64_at(64),
+            directory_table: u64_at(72),
+            fragment_table: u64_at(80),
+            metadata: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<Vec<Entry>> {
+        let inode = self.lookup(path)?;
+        self.entries(&inode)
+    }
+
+    /// Reads a file, following symlinks
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let inode = self.lookup(path)?;
+        let mut data = Vec::new();
+        self.copy_file(&inode, &mut data)?;
+        Ok(data)
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_ok()
+    }
+
+    /// Writes the whole filesystem to `dest`, like `--appimage-extract` does
+    pub fn extract(&self, dest: &Path) -> Result<()> {
+        fs::create_dir_all(dest)?;
+        let root = self.inode(self.root_inode)?;
+        self.extract_dir(&root, dest)
+    }
+
+    fn extract_dir(&self, dir: &Inode, dest: &Path) -> Result<()> {
+        for entry in self.entries(dir)? {
+            if entry.name.contains('/') || entry.name == ".." || entry.name == "." {
+                continue;
+            }
+            let target = dest.join(&entry.name);
+            let inode = self.inode(entry.inode)?;
+            match &inode.kind {
+                Kind::Dir { .. } => {
+                    fs::create_dir_all(&target)?;
+                    self.extract_dir(&inode, &target)?;
+                    fs::set_permissions(&target, Permissions::from_mode(inode.mode as u32))?;
+                }
+                Kind::File { .. } => {
+                    let mut output = File::create(&target)?;
+                    self.copy_file(&inode, &mut output)?;
+                    fs::set_permissions(&target, Permissions::from_mode(inode.mode as u32))?;
+                }
+                Kind::Symlink(link) => {
+                    let _ = fs::remove_file(&target);
+                    symlink(link, &target)?;
+                }
+                Kind::Other => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, path: &str) -> Result<Inode> {
+        let components = |path: &str| -> Vec<String> {
+            path.split('/')
+                .filter(|c| !c.is_empty())
+                .map(String::from)
+                .collect()
+        };
+
+        let mut pending: VecDeque<String> = components(path).into();
+        let mut stack = vec![self.inode(self.root_inode)?];
+        let mut links = 0;
+
+        while let Some(component) = pending.pop_front() {
+            match component.as_str() {
+                "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let dir = stack.last().unwrap();
+            let entry = self
+                .entries(dir)?
+                .into_iter()
+                .find(|entry| entry.name == component)
+                .ok_or_else(|| error!(format!("{} not found in AppImage", path)))?;
+            let inode = self.inode(entry.inode)?;
+
+            if let Kind::Symlink(target) = &inode.kind {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(error!(format!("Too many symlinks resolving {}", path)));
+                }
+                if target.starts_with('/') {
+                    stack.truncate(1);
+                }
+                for component in components(target).into_iter().rev() {
+                    pending.push_front(component);
+                }
+                continue;
+            }
+            stack.push(inode);
+        }
+        Ok(stack.pop().unwrap())
+    }
+
+    fn entries(&self, dir: &Inode) -> Result<Vec<Entry>> {
+        let Kind::Dir {
+            block,
+            offset,
+            size,
+        } = dir.kind
+        else {
+            return Err(error!("Not a directory"));
+        };
+
+        let mut entries = Vec::new();
+        // The stored size counts the implicit `.` and `..` entries
+        let mut remaining = (size as usize).saturating_sub(3);
+        let mut cursor = Cursor {
+            fs: self,
+            block: self.directory_table + block as u64,
+            offset: offset as usize,
+        };
+
+        while remaining >= 12 {
+            let count = cursor.u32()? + 1;
+            let start = cursor.u32()?;
+            let _inode_number = cursor.u32()?;
+            remaining -= 12;
+
+            for _ in 0..count {
+                let inode_offset = cursor.u16()?;
+                let _inode_delta = cursor.u16()?;
+                let kind = cursor.u16()?;
+                let name_size = cursor.u16()? as usize + 1;
+                let name = String::from_utf8_lossy(&cursor.read(name_size)?).to_string();
+                remaining = remaining.saturating_sub(8 + name_size);
+
+                let kind = match kind {
+                    1 | 8 => EntryKind::Dir,
+                    2 | 9 => EntryKind::File,
+                    3 | 10 => EntryKind::Symlink,
+                    _ => EntryKind::Other,
+                };
+                entries.push(Entry {
+                    name,
+                    kind,
+                    inode: ((start as u64) << 16) | inode_offset as u64,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    fn inode(&self, reference: u64) -> Result<Inode> {
+        let mut cursor = Cursor {
+            fs: self,
+            block: self.inode_table + (reference >> 16),
+            offset: (reference & 0xFFFF) as usize,
+        };
+
+        let kind = cursor.u16()?;
+        let mode = cursor.u16()?;
+        let _header = cursor.read(12)?;
+
+        let kind = match kind {
+            1 => {
+                let block = cursor.u32()?;
+                let _link_count = cursor.u32()?;
+                let size = cursor.u16()? as u32;
+                let offset = cursor.u16()?;
+                Kind::Dir {
+                    block,
+                    offset,
+                    size,
+                }
+            }
+            8 => {
+                let _link_count = cursor.u32()?;
+                let size = cursor.u32()?;
+                let block = cursor.u32()?;
+                let _parent = cursor.u32()?;
+                let _index_count = cursor.u16()?;
+                let offset = cursor.u16()?;
+                Kind::Dir {
+                    block,
+                    offset,
+                    size,
+                }
+            }
+            2 | 9 => {
+                let (blocks_start, size, fragment, fragment_offset) = if kind == 2 {
+                    let blocks_start = cursor.u32()? as u64;
+                    let fragment = cursor.u32()?;
+                    let fragment_offset = cursor.u32()?;
+                    let size = cursor.u32()? as u64;
+                    (blocks_start, size, fragment, fragment_offset)
+                } else {
+                    let blocks_start = cursor.u64()?;
+                    let size = cursor.u64()?;
+                    let _sparse = cursor.u64()?;
+                    let _link_count = cursor.u32()?;
+                    let fragment = cursor.u32()?;
+                    let fragment_offset = cursor.u32()?;
+                    let _xattr = cursor.u32()?;
+                    (blocks_start, size, fragment, fragment_offset)
+                };
+                let block_size = self.block_size as u64;
+                let count = if fragment == NO_FRAGMENT {
+                    size.div_ceil(block_size)
+                } else {
+                    size / block_size
+                };
+                let block_sizes = (0..count)
+                    .map(|_| cursor.u32())
+                    .collect::<Result<Vec<_>>>()?;
+                Kind::File {
+                    blocks_start,
+                    size,
+                    fragment,
+                    fragment_offset,
+                    block_sizes,
+                }
+            }
+            3 | 10 => {
+                let _link_count = cursor.u32()?;
+                let target_size = cursor.u32()? as usize;
+                let target = String::from_utf8_lossy(&cursor.read(target_size)?).to_string();
+                Kind::Symlink(target)
+            }
+            _ => Kind::Other,
+        };
+
+        Ok(Inode {
+            mode: mode & 0o7777,
+            kind,
+        })
+    }
+
+    fn copy_file<W: Write>(&self, inode: &Inode, output: &mut W) -> Result<()> {
+        let Kind::File {
+            blocks_start,
+            size,
+            fragment,
+            fragment_offset,
+            block_sizes,
+        } = &inode.kind
+        else {
+            return Err(error!("Not a regular file"));
+        };
+
+        let mut remaining = *size;
+        let mut position = *blocks_start;
+        for &stored in block_sizes {
+            let expected = remaining.min(self.block_size as u64) as usize;
+            let length = (stored & !UNCOMPRESSED_BLOCK) as u64;
+            if length == 0 {
+                output.write_all(&vec![0u8; expected])?;
+            } else {
+                let data = self.read_at(position, length as usize)?;
+                if stored & UNCOMPRESSED_BLOCK != 0 {
+                    output.write_all(&data)?;
+                } else {
+                    output.write_all(&self.decompress(&data, self.block_size as usize)?)?;
+                }
+                position += length;
+            }
+            remaining -= expected as u64;
+        }
+
+        if *fragment != NO_FRAGMENT && remaining > 0 {
+            let block = self.fragment(*fragment)?;
+            let start = *fragment_offset as usize;
+            let end = start + remaining as usize;
+            let data = block
+                .get(start..end)
+                .ok_or_else(|| error!("Corrupted squashfs fragment"))?;
+            output.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    fn fragment(&self, index: u32) -> Result<Vec<u8>> {
+        if index >= self.fragment_count {
+            return Err(error!("Corrupted squashfs fragment index"));
+        }
+        let pointer = self.read_at(self.fragment_table + (index as u64 / 512) * 8, 8)?;
+        let pointer = u64::from_le_bytes(pointer.try_into().unwrap());
+        let mut cursor = Cursor {
+            fs: self,
+            block: pointer,
+            offset: (index as usize % 512) * 16,
+        };
+        let start = cursor.u64()?;
+        let stored = cursor.u32()?;
+
+        let data = self.read_at(start, (stored & !UNCOMPRESSED_BLOCK) as usize)?;
+        if stored & UNCOMPRESSED_BLOCK != 0 {
+            Ok(data)
+        } else {
+            self.decompress(&data, self.block_size as usize)
+        }
+    }
+
+    /// Returns the decompressed block and the position of the following one
+    fn metadata_block(&self, position: u64) -> Result<MetadataBlock> {
+        if let Some(block) = self.metadata.borrow().get(&position) {
+            return Ok(block.clone());
+        }
+
+        let header = self.read_at(position, 2)?;
+        let header = u16::from_le_bytes([header[0], header[1]]);
+        let length = (header & !UNCOMPRESSED_METADATA) as usize;
+        let data = self.read_at(position + 2, length)?;
+        let data = if header & UNCOMPRESSED_METADATA != 0 {
+            data
+        } else {
+            self.decompress(&data, METADATA_SIZE)?
+        };
+
+        let block = Rc::new((data, position + 2 + length as u64));
+        self.metadata.borrow_mut().insert(position, block.clone());
+        Ok(block)
+    }
+
+    fn read_at(&self, position: u64, length: usize) -> Result<Vec<u8>> {
+        let position = self
+            .offset
+            .checked_add(position)
+            .ok_or_else(|| error!("Corrupted squashfs position"))?;
+        let mut data = vec![0u8; length];
+        self.file
+            .read_exact_at(&mut data, position)
+            .context(error!("Unexpected end of squashfs filesystem"))?;
+        Ok(data)
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(max_size);
+        match self.compression {
+            Compression::Gzip => {
+                flate2::read::ZlibDecoder::new(data).read_to_end(&mut output)?;
+            }
+            Compression::Xz => {
+                lzma_rs::xz_decompress(&mut &data[..], &mut output)
+                    .map_err(|err| error!(format!("xz: {}", err)))?;
+            }
+            Compression::Lz4 => {
+                output = lz4_flex::block::decompress(data, max_size)
+                    .map_err(|err| error!(format!("lz4: {}", err)))?;
+            }
+            Compression::Zstd => {
+                ruzstd::decoding::StreamingDecoder::new(data)
+                    .map_err(|err| error!(format!("zstd: {}", err)))?
+                    .read_to_end(&mut output)?;
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    const INODES: usize = 101;
+    const DIRECTORY: usize = 171;
+
+    /// An uncompressed filesystem holding `hello.txt`, laid out as the
+    /// superblock, the file's data block, the inode and directory tables
+    fn image() -> Vec<u8> {
+        let mut data = vec![0u8; 96];
+        data[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        data[12..16].copy_from_slice(&4096u32.to_le_bytes());
+        data[20..22].copy_from_slice(&1u16.to_le_bytes());
+        data[28..30].copy_from_slice(&4u16.to_le_bytes());
+        data[40..48].copy_from_slice(&202u64.to_le_bytes());
+        data[64..72].copy_from_slice(&(INODES as u64).to_le_bytes());
+        data[72..80].copy_from_slice(&(DIRECTORY as u64).to_le_bytes());
+        data[80..88].copy_from_slice(&202u64.to_le_bytes());
+        data.extend(b"hello");
+
+        // Root directory then the file, each behind a 16 byte header
+        let mut inodes = Vec::new();
+        inodes.extend([1u16, 0o755]);
+        inodes.extend([0u16; 6]);
+        inodes.extend([0u16, 0, 2, 0, 32, 0, 0, 0]);
+        inodes.extend([2u16, 0o644]);
+        inodes.extend([0u16; 6]);
+        let mut inodes: Vec<u8> = inodes.iter().flat_map(|v| v.to_le_bytes()).collect();
+        for value in [96, NO_FRAGMENT, 0, 5, 5 | UNCOMPRESSED_BLOCK] {
+            inodes.extend(value.to_le_bytes());
+        }
+        data.extend((UNCOMPRESSED_METADATA | inodes.len() as u16).to_le_bytes());
+        data.extend(inodes);
+
+        let mut directory = Vec::new();
+        for value in [0u32, 0, 1] {
+            directory.extend(value.to_le_bytes());
+        }
+        for value in [32u16, 1, 2, 8] {
+            directory.extend(value.to_le_bytes());
+        }
+        directory.extend(b"hello.txt");
+        data.extend((UNCOMPRESSED_METADATA | directory.len() as u16).to_le_bytes());
+        data.extend(directory);
+        data
+    }
+
+    fn open(data: &[u8]) -> (NamedTempFile, Result<SquashFs>) {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), data).unwrap();
+        let fs = SquashFs::open_at(file.path(), 0);
+        (file, fs)
+    }
+
+    #[test]
+    fn read_files() {
+        let (_file, fs) = open(&image());
+        let fs = fs.unwrap();
+        let names: Vec<_> = fs
+            .read_dir("/")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["hello.txt"]);
+        assert_eq!(fs.read_file("hello.txt").unwrap(), b"hello");
+        assert!(!fs.exists("missing"));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = image();
+        for len in [0, 50, 96, DIRECTORY + 10] {
+            assert!(open(&data[..len]).1.is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn malformed_superblock() {
+        let mut data = image();
+        data[0] = b'x';
+        assert!(open(&data).1.is_err());
+
+        for block_size in [0u32, 3000, 1 << 30] {
+            let mut data = image();
+            data[12..16].copy_from_slice(&block_size.to_le_bytes());
+            assert!(open(&data).1.is_err(), "{}", block_size);
+        }
+
+        let mut data = image();
+        data[64..72].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(open(&data).1.is_err());
+    }
+
+    #[test]
+    fn malformed_tables() {
+        // Root directory listing entries far past its metadata block
+        let mut data = image();
+        data[INODES + 2 + 24..INODES + 2 + 26].copy_from_slice(&u16::MAX.to_le_bytes());
+        let (_file, fs) = open(&data);
+        assert!(fs.unwrap().read_dir("/").is_err());
+
+        // File claiming 4 GiB, more block sizes than the table holds
+        let mut data = image();
+        data[INODES + 2 + 32 + 28..INODES + 2 + 32 + 32].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (_file, fs) = open(&data);
+        assert!(fs.unwrap().read_file("hello.txt").is_err());
+
+        // File data starting past the end of the image
+        let mut data = image();
+        data[INODES + 2 + 32 + 16..INODES + 2 + 32 + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (_file, fs) = open(&data);
+        assert!(fs.unwrap().read_file("hello.txt").is_err());
+
+        // Metadata block longer than the image
+        let mut data = image();
+        data[DIRECTORY..DIRECTORY + 2].copy_from_slice(&0x7FFFu16.to_le_bytes());
+        let (_file, fs) = open(&data);
+        assert!(fs.unwrap().read_dir("/").is_err());
+    }
+}
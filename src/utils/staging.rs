@@ -0,0 +1,174 @@
+use crate::utils::config::Config;
+use anyhow::Result;
+use std::{
+    fs,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+/// Link inside the app folder pointing at the version in use
+pub const CURRENT: &str = "current";
+
+/// A version being installed next to the current one, it only becomes the
+/// current version once it is downloaded, verified and integrated
+pub struct Staging {
+    pub name: String,
+    /// Version folder name inside the app folder
+    pub version: String,
+    /// Holds one folder per installed version and the `current` link
+    pub app_folder: PathBuf,
+    /// Temporary folder on the same filesystem the new version is built in
+    pub folder: PathBuf,
+    /// Integration files outside the app folder and what they held before
+    backups: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl Staging {
+    pub fn new(config: &Config, name: &str, version: &str) -> Result<Self> {
+        let appimage_dir = config.appimage_dir()?;
+        let folder = appimage_dir.join(format!(".{}.staging", name));
+        if folder.exists() {
+            // Left over from an interrupted install
+            fs::remove_dir_all(&folder)?;
+        }
+        fs::create_dir_all(&folder)?;
+        Ok(Staging {
+            name: name.to_string(),
+            version: version.to_string(),
+            app_folder: appimage_dir.join(name),
+            folder,
+            backups: Vec::new(),
+        })
+    }
+
+    /// Where the new AppImage is downloaded to
+    pub fn file_path(&self) -> String {
+        self.folder
+            .join(format!("{}.appimage", self.name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Stable path of the version in use, referenced by the desktop entry
+    pub fn current(&self) -> PathBuf {
+        self.app_folder.join(CURRENT)
+    }
+
+    /// Where the staged version lives once committed
+    pub fn version_folder(&self) -> PathBuf {
+        let version = self.version.replace(['/', '\\'], "_");
+        let version = version.trim_start_matches('.');
+        self.app_folder.join(match version {
+            "" | CURRENT => "unknown",
+            version => version,
+        })
+    }
+
+    /// Writes a file outside the app folder, keeping its previous content so
+    /// a failed install can put it back
+    pub fn write(&mut self, path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+        if !self.backups.iter().any(|(backup, _)| backup == path) {
+            let previous = match path.is_file() {
+                true => Some(fs::read(path)?),
+                false => None,
+            };
+            self.backups.push((path.to_path_buf(), previous));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Commits when the install succeeded and rolls back otherwise
+    pub fn finish<T>(self, result: Result<T>) -> Result<T> {
+        match result {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Moves the staged version into the app folder and makes it current,
+    /// earlier versions are left in place
+    pub fn commit(self) -> Result<()> {
+        let old_folder = self
+            .app_folder
+            .with_file_name(format!(".{}.old", self.name));
+        if old_folder.exists() {
+            fs::remove_dir_all(&old_folder)?;
+        }
+        // Apps installed before versioned folders keep everything at the top
+        let flat = self.app_folder.exists() && !self.current().is_symlink();
+        if flat {
+            if let Err(err) = fs::rename(&self.app_folder, &old_folder) {
+                self.rollback();
+                return Err(err.into());
+            }
+        }
+
+        let version_folder = self.version_folder();
+        let replaced = version_folder.with_file_name(format!(
+            ".{}.old",
+            version_folder
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        ));
+        let moved = fs::create_dir_all(&self.app_folder)
+            .and_then(|_| match version_folder.exists() {
+                true => fs::rename(&version_folder, &replaced),
+                false => Ok(()),
+            })
+            .and_then(|_| fs::rename(&self.folder, &version_folder))
+            .and_then(|_| switch_current(&self.app_folder, &version_folder));
+        if let Err(err) = moved {
+            if !version_folder.exists() && replaced.exists() {
+                let _ = fs::rename(&replaced, &version_folder);
+            }
+            if flat {
+                let _ = fs::remove_dir_all(&self.app_folder);
+                let _ = fs::rename(&old_folder, &self.app_folder);
+            }
+            self.rollback();
+            return Err(err.into());
+        }
+
+        if replaced.exists() {
+            fs::remove_dir_all(&replaced)?;
+        }
+        if old_folder.exists() {
+            fs::remove_dir_all(&old_folder)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the staged version and restores the integration files it touched
+    pub fn rollback(self) {
+        let _ = fs::remove_dir_all(&self.folder);
+        for (path, previous) in self.backups.into_iter().rev() {
+            let _ = match previous {
+                Some(content) => fs::write(&path, content),
+                None => fs::remove_file(&path),
+            };
+        }
+    }
+}
+
+/// Points the `current` link of an app at one of its version folders,
+/// replacing the link atomically
+pub fn switch_current(app_folder: &Path, version_folder: &Path) -> std::io::Result<()> {
+    let target = version_folder
+        .strip_prefix(app_folder)
+        .unwrap_or(version_folder);
+    let link = app_folder.join(format!(".{}.tmp", CURRENT));
+    let _ = fs::remove_file(&link);
+    symlink(target, &link)?;
+    fs::rename(&link, app_folder.join(CURRENT))
+}
@@ -0,0 +1,101 @@
+use crate::utils::{config::Config, paths::Paths};
+use std::{env, fs, sync::OnceLock};
+
+/// Token sent to the github api, read once from `$GITHUB_TOKEN` or
+/// `$GH_TOKEN`, then the config file, then the hosts file of the `gh` cli
+pub fn github_token() -> Option<&'static str> {
+    static TOKEN: OnceLock<Option<String>> = OnceLock::new();
+    TOKEN
+        .get_or_init(|| {
+            ["GITHUB_TOKEN", "GH_TOKEN"]
+                .iter()
+                .find_map(|var| env::var(var).ok())
+                .or_else(|| Config::load().ok()?.github_token)
+                .or_else(gh_token)
+                .map(|token| token.trim().to_string())
+                .filter(|token| !token.is_empty())
+        })
+        .as_deref()
+}
+
+/// The `oauth_token` of `github.com` in `~/.config/gh/hosts.yml`, tokens
+/// kept in the system keyring by newer `gh` versions are not read
+fn gh_token() -> Option<String> {
+    let path = match env::var_os("GH_CONFIG_DIR") {
+        Some(dir) => dir.into(),
+        None => Paths.config_home().ok()?.join("gh"),
+    };
+    hosts_token(&fs::read_to_string(path.join("hosts.yml")).ok()?)
+}
+
+/// Scans the `github.com:` block of `hosts.yml` for its `oauth_token`
+fn hosts_token(hosts: &str) -> Option<String> {
+    let mut in_github = false;
+    for line in hosts.lines() {
+        if !line.starts_with([' ', '\t']) {
+            in_github = line.trim_end() == "github.com:";
+            continue;
+        }
+        if let Some(token) = line.trim().strip_prefix("oauth_token:") {
+            if in_github {
+                return Some(token.trim().trim_matches(['"', '\'']).to_string());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_host() {
+        let hosts = "github.com:\n    user: me\n    oauth_token: gho_abc\n    git_protocol: ssh\n";
+        assert_eq!(hosts_token(hosts).as_deref(), Some("gho_abc"));
+    }
+
+    #[test]
+    fn quoted_token() {
+        let hosts = "github.com:\n    oauth_token: \"gho_abc\"\n";
+        assert_eq!(hosts_token(hosts).as_deref(), Some("gho_abc"));
+        let hosts = "github.com:\n    oauth_token: 'gho_abc'\n";
+        assert_eq!(hosts_token(hosts).as_deref(), Some("gho_abc"));
+    }
+
+    #[test]
+    fn other_hosts_are_ignored() {
+        let hosts = "\
+ghe.example.com:
+    oauth_token: ghe_token
+github.com:
+    user: me
+    oauth_token: gho_abc
+";
+        assert_eq!(hosts_token(hosts).as_deref(), Some("gho_abc"));
+        assert_eq!(
+            hosts_token("ghe.example.com:\n    oauth_token: ghe_token\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn multi_account_layout() {
+        // Newer `gh` versions also list every account under `users:`
+        let hosts = "\
+github.com:
+    users:
+        me:
+            oauth_token: gho_abc
+    oauth_token: gho_abc
+    user: me
+";
+        assert_eq!(hosts_token(hosts).as_deref(), Some("gho_abc"));
+    }
+
+    #[test]
+    fn no_token() {
+        assert_eq!(hosts_token(""), None);
+        assert_eq!(hosts_token("github.com:\n    user: me\n"), None);
+    }
+}
@@ -1,5 +1,4 @@
 use anyhow::Result;
-use std::env;
 use std::process::Command;
 
 pub struct Tools;
@@ -9,8 +8,4 @@ impl Tools {
         Command::new("clear").status()?;
         Ok(())
     }
-
-    pub fn get_user(&self) -> Result<String> {
-        Ok(env::var("USER")?)
-    }
 }
@@ -0,0 +1,327 @@
+use crate::utils::errors::error;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// How a release tag numbers its versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `1.2.3`
+    Semver,
+    /// A year first, e.g. `2024.01.15`
+    Calendar,
+    /// Any other run of numbers, e.g. `1.2.3.4` or `release-5`
+    Numeric,
+    /// A numbered version marked unstable, e.g. `v1.0-rc2`
+    Prerelease,
+    /// The date of a build, e.g. `nightly-20240101`
+    CommitDate,
+    /// Nothing to compare, e.g. `continuous`
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Identifier {
+    Number(u64),
+    Text(String),
+}
+
+/// A version read from a release tag or a package version, compared by its
+/// numbers rather than as text
+#[derive(Debug, Clone)]
+pub struct Version {
+    /// The tag as it was published
+    pub tag: String,
+    pub kind: Kind,
+    numbers: Vec<u64>,
+    pre: Vec<Identifier>,
+}
+
+impl Version {
+    pub fn parse(tag: &str) -> Self {
+        let mut version = Version {
+            tag: tag.to_string(),
+            kind: Kind::Unknown,
+            numbers: Vec::new(),
+            pre: Vec::new(),
+        };
+        // Prefixes such as `v`, `release-` or `nightly-` carry no ordering
+        let Some(start) = tag.find(|c: char| c.is_ascii_digit()) else {
+            return version;
+        };
+        let rest = &tag[start..];
+        let rest = rest.split('+').next().unwrap_or_default();
+
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (numbers, mut remainder) = rest.split_at(end);
+        version.numbers = numbers
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse().unwrap_or(u64::MAX))
+            .collect();
+
+        // Dates written with dashes, e.g. `2024-01-15`
+        if version.numbers.len() == 1 && is_year(version.numbers[0]) {
+            while let Some(part) = remainder
+                .strip_prefix('-')
+                .and_then(|part| part.get(..2))
+                .filter(|part| part.chars().all(|c| c.is_ascii_digit()))
+            {
+                version.numbers.push(part.parse().unwrap_or_default());
+                remainder = &remainder[3..];
+            }
+        }
+
+        if let [date] = version.numbers[..] {
+            if is_build_date(date, numbers.len()) {
+                // Whatever follows a build date is usually a commit hash
+                version.kind = Kind::CommitDate;
+                return version;
+            }
+        }
+
+        version.pre = remainder
+            .split(['-', '_', '.', '~'])
+            .flat_map(identifiers)
+            .collect();
+        version.kind = match (version.numbers.len(), version.pre.is_empty()) {
+            (_, false) => Kind::Prerelease,
+            (2.., true) if is_year(version.numbers[0]) => Kind::Calendar,
+            (3, true) => Kind::Semver,
+            _ => Kind::Numeric,
+        };
+        version
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    /// Whether `latest` should replace this version, versions that cannot be
+    /// compared are replaced when their tags differ
+    pub fn is_older_than(&self, latest: &Version) -> bool {
+        match self.partial_cmp(latest) {
+            Some(ordering) => ordering == Ordering::Less,
+            None => self.tag != latest.tag,
+        }
+    }
+
+    /// Reads the version of a tag through the filter of an app that shares
+    /// its repository with other products, keeping the whole tag
+    pub fn from_tag(tag: &str, filter: Option<&TagFilter>) -> Self {
+        let mut version =
+            Version::parse(filter.and_then(|filter| filter.version(tag)).unwrap_or(tag));
+        version.tag = tag.to_string();
+        version
+    }
+
+    /// The closest semver version, used to match version requirements
+    pub fn to_semver(&self) -> Option<semver::Version> {
+        if self.kind == Kind::Unknown {
+            return None;
+        }
+        let number = |index: usize| self.numbers.get(index).copied().unwrap_or_default();
+        let mut version = semver::Version::new(number(0), number(1), number(2));
+        if self.is_prerelease() {
+            let pre = self
+                .pre
+                .iter()
+                .map(|identifier| match identifier {
+                    Identifier::Number(number) => number.to_string(),
+                    Identifier::Text(text) => text.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(".");
+            version.pre = semver::Prerelease::new(&pre).ok()?;
+        }
+        Some(version)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Version {
+    /// Missing numbers count as zero and a release is newer than its
+    /// pre-releases. Build dates only compare with other build dates
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.kind == Kind::Unknown || other.kind == Kind::Unknown {
+            return None;
+        }
+        if (self.kind == Kind::CommitDate) != (other.kind == Kind::CommitDate) {
+            return None;
+        }
+        let len = self.numbers.len().max(other.numbers.len());
+        let number = |numbers: &[u64], index: usize| numbers.get(index).copied().unwrap_or(0);
+        let numbers = (0..len)
+            .map(|index| number(&self.numbers, index).cmp(&number(&other.numbers, index)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal);
+        let pre = match (self.pre.is_empty(), other.pre.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => self.pre.cmp(&other.pre),
+        };
+        Some(numbers.then(pre))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tag)
+    }
+}
+
+/// Tells the releases of one product apart in a repository that releases
+/// several, e.g. `desktop-v1.2.0` next to `cli-v3.0.1`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct TagFilter(String);
+
+impl TagFilter {
+    /// Tags starting with `prefix`, the version is what follows it
+    pub fn prefix(prefix: &str) -> Self {
+        TagFilter(format!("^{}(.+)$", regex::escape(prefix)))
+    }
+
+    pub fn matches(&self, tag: &str) -> bool {
+        self.regex().is_some_and(|regex| regex.is_match(tag))
+    }
+
+    /// The version part of `tag`, its first capture group or else whatever
+    /// follows the match
+    pub fn version<'a>(&self, tag: &'a str) -> Option<&'a str> {
+        let captures = self.regex()?.captures(tag)?;
+        let version = match captures.get(1) {
+            Some(group) => group.as_str(),
+            None => &tag[captures.get(0)?.end()..],
+        };
+        Some(version).filter(|version| !version.is_empty())
+    }
+
+    fn regex(&self) -> Option<Regex> {
+        Regex::new(&self.0).ok()
+    }
+}
+
+impl FromStr for TagFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(pattern: &str) -> Result<Self> {
+        Regex::new(pattern).context(error!(format!("{} is not a valid regex", pattern)))?;
+        Ok(TagFilter(pattern.to_string()))
+    }
+}
+
+impl fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn is_year(number: u64) -> bool {
+    (1970..=2999).contains(&number)
+}
+
+/// `YYYYMMDD`, optionally followed by the time of day
+fn is_build_date(number: u64, digits: usize) -> bool {
+    let date = match digits {
+        8 => number,
+        10 | 12 | 14 => number / 10u64.pow(digits as u32 - 8),
+        _ => return false,
+    };
+    let (year, month, day) = (date / 10000, date / 100 % 100, date % 100);
+    is_year(year) && (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+/// Splits `rc2` into `rc` and `2`
+fn identifiers(part: &str) -> Vec<Identifier> {
+    let mut identifiers = Vec::new();
+    let mut rest = part;
+    while let Some(first) = rest.chars().next() {
+        let numeric = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != numeric)
+            .unwrap_or(rest.len());
+        let (run, next) = rest.split_at(end);
+        identifiers.push(match numeric {
+            true => Identifier::Number(run.parse().unwrap_or(u64::MAX)),
+            false => Identifier::Text(run.to_lowercase()),
+        });
+        rest = next;
+    }
+    identifiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        for (tag, kind) in [
+            ("v1.2.3", Kind::Semver),
+            ("2024.01.15", Kind::Calendar),
+            ("2024-01-15", Kind::Calendar),
+            ("1.2.3.4", Kind::Numeric),
+            ("release-5", Kind::Numeric),
+            ("v1.0-rc2", Kind::Prerelease),
+            ("nightly-20240101", Kind::CommitDate),
+            ("20240101-abc1234", Kind::CommitDate),
+            ("continuous", Kind::Unknown),
+        ] {
+            assert_eq!(Version::parse(tag).kind, kind, "{}", tag);
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        let older = |a: &str, b: &str| Version::parse(a) < Version::parse(b);
+        assert!(older("v1.0-rc2", "v1.0"));
+        assert!(older("v1.0-rc2", "v1.0-rc10"));
+        assert!(older("v1.9.0", "v1.10.0"));
+        assert!(older("2024.01.15", "2024.2.1"));
+        assert!(older("1.2.3.4", "1.2.3.5"));
+        assert!(older("release-5", "release-12"));
+        assert!(older("nightly-20240101", "nightly-20240215"));
+        assert_eq!(Version::parse("v1.2"), Version::parse("1.2.0"));
+    }
+
+    #[test]
+    fn mixed_kinds_do_not_compare() {
+        let release = Version::parse("v1.2.3");
+        let nightly = Version::parse("nightly-20240101");
+        assert_eq!(release.partial_cmp(&nightly), None);
+        assert_eq!(release.partial_cmp(&Version::parse("continuous")), None);
+        // Tags that cannot be compared are replaced when they differ
+        assert!(release.is_older_than(&nightly));
+        assert!(!release.is_older_than(&Version::parse("v1.2.3")));
+    }
+
+    #[test]
+    fn semver() {
+        let version = Version::parse("v1.0-rc2").to_semver().unwrap();
+        assert_eq!(version.to_string(), "1.0.0-rc.2");
+        assert_eq!(Version::parse("continuous").to_semver(), None);
+    }
+
+    #[test]
+    fn tag_filter() {
+        let filter = TagFilter::prefix("desktop-v");
+        assert!(filter.matches("desktop-v1.2.0"));
+        assert!(!filter.matches("cli-v3.0.1"));
+        assert_eq!(filter.version("desktop-v1.2.0"), Some("1.2.0"));
+
+        let version = Version::from_tag("desktop-v1.2.0", Some(&filter));
+        assert_eq!(version.tag, "desktop-v1.2.0");
+        assert_eq!(version.kind, Kind::Semver);
+        assert!("[".parse::<TagFilter>().is_err());
+    }
+}
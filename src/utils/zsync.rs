@@ -0,0 +1,502 @@
+use crate::utils::{checksum::Checksum, errors::error, progress};
+use anyhow::{Context, Result};
+use color_print::cformat;
+use indicatif::{ProgressBar, ProgressStyle};
+use md4::{Digest, Md4};
+use reqwest::{
+    header::{HeaderValue, RANGE, USER_AGENT},
+    Client, StatusCode,
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File, Permissions},
+    io::Write,
+    os::unix::{fs::FileExt, prelude::PermissionsExt},
+    path::Path,
+};
+
+/// Checksums of one block of the target file
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    rsum: u32,
+    md4: Vec<u8>,
+}
+
+/// A `.zsync` control file describing the AppImage it was made for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zsync {
+    pub filename: String,
+    /// Absolute url of the target file
+    pub url: String,
+    pub sha1: Option<Checksum>,
+    pub length: u64,
+    blocksize: usize,
+    /// Consecutive blocks that must match before a block is trusted
+    seq_matches: usize,
+    rsum_mask: u32,
+    blocks: Vec<Block>,
+}
+
+impl Zsync {
+    pub async fn fetch(url: &str) -> Result<Self> {
+        let response = Client::new()
+            .get(url)
+            .header(USER_AGENT, HeaderValue::from_static("reqwest"))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(error!(format!("Failed to fetch {}", url)));
+        }
+        let body = response.bytes().await?;
+        Zsync::parse(&body, url)
+    }
+
+    /// Looks for a control file published next to `url`
+    pub async fn find(url: &str) -> Option<Self> {
+        Zsync::fetch(&format!("{}.zsync", url)).await.ok()
+    }
+
+    /// Reads the header and the block checksums that follow it, relative
+    /// target urls are resolved against `zsync_url`
+    pub fn parse(data: &[u8], zsync_url: &str) -> Result<Self> {
+        let end = data
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .context(error!("Malformed zsync file"))?;
+        let header = String::from_utf8_lossy(&data[..end]);
+
+        let mut filename = String::new();
+        let mut url = None;
+        let mut sha1 = None;
+        let mut length = 0;
+        let mut blocksize = 2048;
+        let mut hash_lengths = (1, 4, 16);
+        for line in header.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "Filename" => filename = value.to_string(),
+                "URL" => url = Some(value.to_string()),
+                "SHA-1" => sha1 = Checksum::from_hex(value),
+                "Length" => {
+                    length = value
+                        .parse()
+                        .context(error!("Malformed zsync file length"))?
+                }
+                "Blocksize" => {
+                    blocksize = value
+                        .parse()
+                        .context(error!("Malformed zsync file blocksize"))?
+                }
+                "Hash-Lengths" => {
+                    let lengths: Vec<usize> = value
+                        .split(',')
+                        .map(|length| length.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .context(error!("Malformed zsync hash lengths"))?;
+                    if let [seq_matches, rsum_bytes, checksum_bytes] = lengths[..] {
+                        hash_lengths = (seq_matches, rsum_bytes, checksum_bytes);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (seq_matches, rsum_bytes, checksum_bytes) = hash_lengths;
+        if blocksize == 0 || !(1..=4).contains(&rsum_bytes) || !(1..=16).contains(&checksum_bytes) {
+            return Err(error!("Unsupported zsync file"));
+        }
+
+        let count = (length as usize).div_ceil(blocksize);
+        let entry_size = rsum_bytes + checksum_bytes;
+        let checksums = &data[end + 2..];
+        if checksums.len() < count * entry_size {
+            return Err(error!("Truncated zsync file"));
+        }
+        // The rolling checksum is stored big endian as `a` then `b`, keeping
+        // only its last `rsum_bytes` bytes
+        let blocks = checksums
+            .chunks(entry_size)
+            .take(count)
+            .map(|entry| {
+                let mut rsum = [0u8; 4];
+                rsum[4 - rsum_bytes..].copy_from_slice(&entry[..rsum_bytes]);
+                Block {
+                    rsum: u32::from_be_bytes(rsum),
+                    md4: entry[rsum_bytes..].to_vec(),
+                }
+            })
+            .collect();
+
+        let url = url.context(error!("The zsync file does not name its target url"))?;
+        Ok(Zsync {
+            filename,
+            url: Zsync::resolve(zsync_url, &url),
+            sha1,
+            length,
+            blocksize,
+            seq_matches,
+            rsum_mask: match rsum_bytes {
+                4 => u32::MAX,
+                bytes => (1 << (8 * bytes)) - 1,
+            },
+            blocks,
+        })
+    }
+
+    /// Rebuilds the target at `file_path` from the blocks of `seed` it shares,
+    /// downloading the rest with range requests. Returns `false` when the
+    /// result cannot be produced this way and a full download is needed
+    pub async fn sync(&self, seed: &Path, file_path: &str, name: &str) -> Result<bool> {
+        let Some(sha1) = &self.sha1 else {
+            return Ok(false);
+        };
+        let seed = File::open(seed)?;
+        let found = {
+            let (zsync, seed) = (self.clone(), seed.try_clone()?);
+            tokio::task::spawn_blocking(move || zsync.match_blocks(&seed)).await??
+        };
+
+        let part_path = format!("{}.zsync-part", file_path);
+        if let Some(parent) = Path::new(file_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let result = self.assemble(&seed, &found, &part_path, name).await;
+        let complete = match result {
+            Ok(complete) => complete && sha1.verify(&part_path).is_ok(),
+            Err(err) => {
+                let _ = fs::remove_file(&part_path);
+                return Err(err);
+            }
+        };
+        if !complete {
+            let _ = fs::remove_file(&part_path);
+            return Ok(false);
+        }
+
+        fs::rename(&part_path, file_path)?;
+        fs::set_permissions(file_path, Permissions::from_mode(0o755))?;
+        Ok(true)
+    }
+
+    async fn assemble(
+        &self,
+        seed: &File,
+        found: &[Option<usize>],
+        part_path: &str,
+        name: &str,
+    ) -> Result<bool> {
+        let missing = found.iter().filter(|offset| offset.is_none()).count();
+        let pb = progress::add(ProgressBar::new((missing * self.blocksize) as u64));
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{msg} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+            )?
+            .progress_chars("=> "),
+        );
+        pb.set_message(cformat!(
+            "<c>Updating {} ({}/{} blocks reused)...",
+            name,
+            found.len() - missing,
+            found.len()
+        ));
+
+        let client = Client::new();
+        let mut output = File::create(part_path)?;
+        let mut block = vec![0u8; self.blocksize];
+        let mut index = 0;
+        while index < found.len() {
+            if let Some(offset) = found[index] {
+                seed.read_exact_at(&mut block, offset as u64)?;
+                output.write_all(&block)?;
+                index += 1;
+                continue;
+            }
+
+            let end = found[index..]
+                .iter()
+                .position(|offset| offset.is_some())
+                .map_or(found.len(), |run| index + run);
+            let from = (index * self.blocksize) as u64;
+            let to = ((end * self.blocksize) as u64).min(self.length);
+            let mut response = client
+                .get(&self.url)
+                .header(USER_AGENT, HeaderValue::from_static("reqwest"))
+                .header(RANGE, format!("bytes={}-{}", from, to - 1))
+                .send()
+                .await?;
+            if response.status() != StatusCode::PARTIAL_CONTENT {
+                pb.finish_and_clear();
+                return Ok(false);
+            }
+            let mut received = 0;
+            while let Some(chunk) = response.chunk().await? {
+                received += chunk.len() as u64;
+                if received > to - from {
+                    break;
+                }
+                output.write_all(&chunk)?;
+                pb.inc(chunk.len() as u64);
+            }
+            if received != to - from {
+                pb.finish_and_clear();
+                return Ok(false);
+            }
+            index = end;
+        }
+
+        output.set_len(self.length)?;
+        output.flush()?;
+        pb.finish_and_clear();
+        Ok(true)
+    }
+
+    /// Finds the offset in `seed` of every target block it contains by
+    /// sliding a rolling checksum over it one byte at a time
+    fn match_blocks(&self, seed: &File) -> Result<Vec<Option<usize>>> {
+        let size = self.blocksize;
+        let mut found = vec![None; self.blocks.len()];
+        let mut seed = Window {
+            file: seed,
+            len: seed.metadata()?.len() as usize,
+            start: 0,
+            buffer: Vec::new(),
+        };
+        let Some(first) = seed.get(0, size)? else {
+            return Ok(found);
+        };
+        let (mut a, mut b) = rsum(first);
+
+        let mut candidates: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (index, block) in self.blocks.iter().enumerate() {
+            candidates.entry(block.rsum).or_default().push(index);
+        }
+
+        let mut offset = 0;
+        loop {
+            let key = (((a as u32) << 16) | b as u32) & self.rsum_mask;
+            let mut matched = false;
+            if let Some(indexes) = candidates.get(&key) {
+                let Some(block) = seed.get(offset, offset + size)? else {
+                    break;
+                };
+                let digest = Md4::digest(block);
+                let next = seed.get(offset + size, offset + 2 * size)?;
+                for &index in indexes {
+                    if found[index].is_none()
+                        && self.strong_matches(index, &digest)
+                        && self.following_matches(next, index)
+                    {
+                        found[index] = Some(offset);
+                        matched = true;
+                    }
+                }
+            }
+
+            if matched {
+                offset += size;
+                let Some(block) = seed.get(offset, offset + size)? else {
+                    break;
+                };
+                (a, b) = rsum(block);
+            } else {
+                let Some(window) = seed.get(offset, offset + size + 1)? else {
+                    break;
+                };
+                let (old, new) = (window[0] as u16, window[size] as u16);
+                a = a.wrapping_sub(old).wrapping_add(new);
+                b = b
+                    .wrapping_sub((size as u16).wrapping_mul(old))
+                    .wrapping_add(a);
+                offset += 1;
+            }
+        }
+        Ok(found)
+    }
+
+    fn strong_matches(&self, index: usize, digest: &[u8]) -> bool {
+        let expected = &self.blocks[index].md4;
+        digest[..expected.len()] == expected[..]
+    }
+
+    /// With short hashes zsync requires the next block to match as well
+    fn following_matches(&self, next_block: Option<&[u8]>, index: usize) -> bool {
+        let next = index + 1;
+        if self.seq_matches < 2 || next + 1 >= self.blocks.len() {
+            return true;
+        }
+        let Some(window) = next_block else {
+            return false;
+        };
+        let (a, b) = rsum(window);
+        let key = (((a as u32) << 16) | b as u32) & self.rsum_mask;
+        key == self.blocks[next].rsum && self.strong_matches(next, &Md4::digest(window))
+    }
+
+    fn resolve(base: &str, url: &str) -> String {
+        if url.contains("://") {
+            return url.to_string();
+        }
+        if url.starts_with('/') {
+            let origin_end = base
+                .find("://")
+                .and_then(|scheme| base[scheme + 3..].find('/').map(|i| scheme + 3 + i))
+                .unwrap_or(base.len());
+            return format!("{}{}", &base[..origin_end], url);
+        }
+        match base.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/{}", dir, url),
+            None => url.to_string(),
+        }
+    }
+}
+
+/// Reads the seed a few MiB at a time, the scan only moves forward
+struct Window<'a> {
+    file: &'a File,
+    len: usize,
+    start: usize,
+    buffer: Vec<u8>,
+}
+
+impl Window<'_> {
+    const SIZE: usize = 4 << 20;
+
+    /// The bytes `from..to` of the file, `None` when it is shorter
+    fn get(&mut self, from: usize, to: usize) -> Result<Option<&[u8]>> {
+        if to > self.len {
+            return Ok(None);
+        }
+        if from < self.start || to > self.start + self.buffer.len() {
+            let end = self.len.min(from + Self::SIZE.max(to - from));
+            self.buffer.resize(end - from, 0);
+            self.file.read_exact_at(&mut self.buffer, from as u64)?;
+            self.start = from;
+        }
+        Ok(Some(&self.buffer[from - self.start..to - self.start]))
+    }
+}
+
+/// The rolling checksum of zsync, `b` weighs the first byte by the block size
+fn rsum(data: &[u8]) -> (u16, u16) {
+    let mut a: u16 = 0;
+    let mut b: u16 = 0;
+    let mut weight = data.len() as u16;
+    for &byte in data {
+        a = a.wrapping_add(byte as u16);
+        b = b.wrapping_add(weight.wrapping_mul(byte as u16));
+        weight = weight.wrapping_sub(1);
+    }
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A control file for `target` the way `zsyncmake` writes it
+    fn control(target: &[u8], blocksize: usize, url: &str) -> Vec<u8> {
+        let mut data = format!(
+            "zsync: 0.6.2\nFilename: App.AppImage\nURL: {}\nLength: {}\nBlocksize: {}\nHash-Lengths: 1,4,16\n\n",
+            url,
+            target.len(),
+            blocksize
+        )
+        .into_bytes();
+        for block in target.chunks(blocksize) {
+            let mut padded = block.to_vec();
+            padded.resize(blocksize, 0);
+            let (a, b) = rsum(&padded);
+            data.extend(a.to_be_bytes());
+            data.extend(b.to_be_bytes());
+            data.extend(Md4::digest(&padded));
+        }
+        data
+    }
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rolling_checksum() {
+        assert_eq!(rsum(&[]), (0, 0));
+        assert_eq!(rsum(&[1, 2, 3]), (6, 3 + 2 * 2 + 3));
+    }
+
+    #[test]
+    fn parse_header_and_blocks() {
+        let target = pseudo_random(10_000, 1);
+        let zsync = Zsync::parse(
+            &control(&target, 2048, "App.AppImage"),
+            "https://example.com/releases/App.AppImage.zsync",
+        )
+        .unwrap();
+        assert_eq!(zsync.filename, "App.AppImage");
+        assert_eq!(zsync.url, "https://example.com/releases/App.AppImage");
+        assert_eq!(zsync.length, 10_000);
+        assert_eq!(zsync.blocks.len(), 5);
+        assert_eq!(zsync.rsum_mask, u32::MAX);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_files() {
+        let target = pseudo_random(10_000, 1);
+        let data = control(&target, 2048, "App.AppImage");
+        assert!(Zsync::parse(&data[..data.len() - 1], "App.AppImage.zsync").is_err());
+        assert!(Zsync::parse(b"zsync: 0.6.2\n", "App.AppImage.zsync").is_err());
+    }
+
+    #[test]
+    fn resolve_urls() {
+        let base = "https://example.com/a/b.zsync";
+        assert_eq!(Zsync::resolve(base, "c"), "https://example.com/a/c");
+        assert_eq!(Zsync::resolve(base, "/c"), "https://example.com/c");
+        assert_eq!(Zsync::resolve(base, "http://other/c"), "http://other/c");
+    }
+
+    #[test]
+    fn match_shifted_blocks() {
+        let target = pseudo_random(4 * 1024, 2);
+        let zsync = Zsync::parse(&control(&target, 1024, "App"), "App.zsync").unwrap();
+
+        // The seed holds the second and fourth block, moved by a few bytes
+        let mut seed = pseudo_random(7, 3);
+        seed.extend(&target[1024..2048]);
+        seed.extend(pseudo_random(300, 4));
+        seed.extend(&target[3072..]);
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&seed).unwrap();
+        let found = zsync.match_blocks(&file).unwrap();
+        assert_eq!(found, vec![None, Some(7), None, Some(7 + 1024 + 300)]);
+    }
+
+    #[test]
+    fn window_crosses_refills() {
+        let data = pseudo_random(2 * Window::SIZE + 100, 5);
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+        let mut window = Window {
+            file: &file,
+            len: data.len(),
+            start: 0,
+            buffer: Vec::new(),
+        };
+        for (from, to) in [
+            (0, 10),
+            (Window::SIZE - 5, Window::SIZE + 5),
+            (data.len() - 10, data.len()),
+        ] {
+            assert_eq!(window.get(from, to).unwrap(), Some(&data[from..to]));
+        }
+        assert_eq!(window.get(data.len() - 10, data.len() + 1).unwrap(), None);
+    }
+}